@@ -0,0 +1,10 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS regions_parent_id;
+
+ALTER TABLE regions
+  DROP COLUMN IF EXISTS parent_id,
+  DROP COLUMN IF EXISTS country_code,
+  DROP COLUMN IF EXISTS latitude,
+  DROP COLUMN IF EXISTS longitude,
+  DROP COLUMN IF EXISTS s2_cell;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+ALTER TABLE regions
+  ADD COLUMN parent_id INT,
+  ADD COLUMN country_code CHAR(2),
+  ADD COLUMN latitude DOUBLE PRECISION,
+  ADD COLUMN longitude DOUBLE PRECISION,
+  ADD COLUMN s2_cell VARCHAR(16);
+
+CREATE INDEX regions_parent_id ON regions (parent_id);
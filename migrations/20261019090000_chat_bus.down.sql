@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS chat_views;
+DROP TABLE IF EXISTS chat_rooms;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+CREATE TABLE chat_rooms (
+  id INT PRIMARY KEY,
+  last_seq BIGINT NOT NULL
+);
+
+CREATE TABLE chat_views (
+  id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+  views BIGINT NOT NULL
+);
+
+INSERT INTO chat_views (views) VALUES (0);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS contest_winners;
+DROP TABLE IF EXISTS contests;
+DROP TABLE IF EXISTS reindeer;
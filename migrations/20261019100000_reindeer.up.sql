@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+CREATE TABLE reindeer (
+  id BIGSERIAL PRIMARY KEY,
+  name TEXT NOT NULL UNIQUE,
+  attributes JSONB NOT NULL DEFAULT '{}',
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE contests (
+  id BIGSERIAL PRIMARY KEY,
+  held_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  entrants TEXT[] NOT NULL,
+  categories JSONB NOT NULL,
+  results JSONB NOT NULL
+);
+
+CREATE INDEX contests_held_at ON contests (held_at);
+
+-- Winners are kept by name so the history survives reindeer leaving the roster
+CREATE TABLE contest_winners (
+  contest_id BIGINT NOT NULL REFERENCES contests (id) ON DELETE CASCADE,
+  category TEXT NOT NULL,
+  reindeer TEXT NOT NULL,
+  PRIMARY KEY (contest_id, category, reindeer)
+);
@@ -0,0 +1,7 @@
+-- Add up migration script here
+
+-- Rooms set back to public keep their row so CHAT_ROOM_ACLS doesn't override them on restart
+CREATE TABLE chat_room_acls (
+  room_id INT PRIMARY KEY,
+  acl JSONB NOT NULL
+);
@@ -0,0 +1,222 @@
+//! Signed session tokens, HS256 JWTs issued at `/auth/token`.
+//!
+//! Whoever sits in front of this service and knows who the user is (an SSO proxy, an internal
+//! tool) asks for a token with the issuer key from `AUTH_ISSUER_KEY`. Tokens are signed with
+//! `AUTH_SECRET`, which every instance has to share.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+  extract::State,
+  http::{header, HeaderMap, HeaderValue, StatusCode},
+  response::{IntoResponse, Response},
+  routing::post,
+  Json, Router,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::days::AppError;
+
+/// How long tokens last unless asked otherwise.
+const DEFAULT_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// The longest a token may last.
+const MAX_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub fn get_routes(auth: Arc<Auth>) -> Router {
+  Router::new()
+    .route("/auth/token", post(issue_token))
+    .with_state(auth)
+}
+
+pub struct Auth {
+  encoding: EncodingKey,
+  decoding: DecodingKey,
+  issuer_key: Option<String>,
+}
+
+impl std::fmt::Debug for Auth {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Auth").finish_non_exhaustive()
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+  /// The user name.
+  pub sub: String,
+  pub iat: u64,
+  pub exp: u64,
+  #[serde(default)]
+  pub admin: bool,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+  Missing,
+  Invalid(jsonwebtoken::errors::Error),
+  Forbidden(&'static str),
+}
+
+impl std::fmt::Display for AuthError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Missing => f.write_str("Missing token"),
+      Self::Invalid(e) => write!(f, "Invalid token: {e}"),
+      Self::Forbidden(reason) => f.write_str(reason),
+    }
+  }
+}
+
+impl std::error::Error for AuthError {}
+
+impl AuthError {
+  pub const fn status(&self) -> StatusCode {
+    match self {
+      Self::Missing | Self::Invalid(_) => StatusCode::UNAUTHORIZED,
+      Self::Forbidden(_) => StatusCode::FORBIDDEN,
+    }
+  }
+}
+
+impl IntoResponse for AuthError {
+  fn into_response(self) -> Response {
+    let mut res = (self.status(), self.to_string()).into_response();
+    if self.status() == StatusCode::UNAUTHORIZED {
+      let _ = res
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+
+    res
+  }
+}
+
+impl Auth {
+  pub fn from_env() -> Self {
+    let secret = std::env::var("AUTH_SECRET").unwrap_or_else(|_| {
+      warn!("AUTH_SECRET is not set, tokens will only be valid on this instance until it restarts");
+      format!("{}{}", ulid::Ulid::new(), ulid::Ulid::new())
+    });
+
+    let issuer_key = std::env::var("AUTH_ISSUER_KEY").ok();
+    if issuer_key.is_none() {
+      warn!("AUTH_ISSUER_KEY is not set, /auth/token will not issue tokens");
+    }
+
+    Self::new(secret.as_bytes(), issuer_key)
+  }
+
+  pub fn new(secret: &[u8], issuer_key: Option<String>) -> Self {
+    Self {
+      encoding: EncodingKey::from_secret(secret),
+      decoding: DecodingKey::from_secret(secret),
+      issuer_key,
+    }
+  }
+
+  pub fn issue(&self, user: String, admin: bool, ttl: Duration) -> Result<String, AppError> {
+    let iat = chrono::Utc::now().timestamp().unsigned_abs();
+    let claims = Claims {
+      sub: user,
+      iat,
+      exp: iat + ttl.as_secs(),
+      admin,
+    };
+
+    Ok(jsonwebtoken::encode(
+      &Header::new(Algorithm::HS256),
+      &claims,
+      &self.encoding,
+    )?)
+  }
+
+  pub fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 5;
+
+    jsonwebtoken::decode::<Claims>(token, &self.decoding, &validation)
+      .map(|el| el.claims)
+      .map_err(AuthError::Invalid)
+  }
+
+  /// Verifies the bearer token in the `Authorization` header, or `token` when the client can't
+  /// set headers, like browsers opening a WebSocket.
+  pub fn authenticate(
+    &self,
+    headers: &HeaderMap,
+    token: Option<&str>,
+  ) -> Result<Claims, AuthError> {
+    let token = bearer(headers).or(token).ok_or(AuthError::Missing)?;
+
+    self.verify(token)
+  }
+
+  pub fn require_admin(&self, headers: &HeaderMap) -> Result<Claims, AuthError> {
+    let claims = self.authenticate(headers, None)?;
+    if !claims.admin {
+      return Err(AuthError::Forbidden("Admin token required"));
+    }
+
+    Ok(claims)
+  }
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+  headers
+    .get(header::AUTHORIZATION)
+    .and_then(|el| el.to_str().ok())
+    .and_then(|el| el.strip_prefix("Bearer "))
+    .map(str::trim)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenRequest {
+  user: String,
+  #[serde(default)]
+  admin: bool,
+  ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenResponse {
+  token: String,
+  expires_in: u64,
+}
+
+async fn issue_token(
+  State(auth): State<Arc<Auth>>,
+  headers: HeaderMap,
+  Json(req): Json<TokenRequest>,
+) -> Result<Json<TokenResponse>, AppError> {
+  let Some(issuer_key) = &auth.issuer_key else {
+    return Err(AuthError::Forbidden("Token issuing is not configured").into());
+  };
+
+  // Not a JWT, the issuer key is a plain shared secret
+  let presented = bearer(&headers).ok_or(AuthError::Missing)?;
+  if !constant_time_eq(presented.as_bytes(), issuer_key.as_bytes()) {
+    return Err(AuthError::Forbidden("Wrong issuer key").into());
+  }
+
+  let user = req.user.trim();
+  if user.is_empty() {
+    return Err(crate::days::BadRequest("User cannot be empty".to_string()).into());
+  }
+
+  let ttl = req
+    .ttl_secs
+    .map_or(DEFAULT_TTL, Duration::from_secs)
+    .min(MAX_TTL);
+
+  Ok(Json(TokenResponse {
+    token: auth.issue(user.to_string(), req.admin, ttl)?,
+    expires_in: ttl.as_secs(),
+  }))
+}
@@ -0,0 +1,695 @@
+use std::{io, mem, sync::Arc};
+
+use anyhow::anyhow;
+use axum::{
+  body::{Bytes, StreamBody},
+  extract::{BodyStream, Query, State},
+  http::{header, HeaderMap},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Json, Router,
+};
+use csv::StringRecord;
+use futures_util::stream::{self, StreamExt};
+use parquet::{
+  data_type::{ByteArray, ByteArrayType, DoubleType, Int32Type},
+  file::{properties::WriterProperties, writer::SerializedFileWriter},
+  schema::parser::parse_message_type,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map};
+use sqlx::{postgres::PgRow, PgPool, Postgres, Row};
+use tokio::sync::mpsc;
+
+use crate::{
+  days::{day_13::MyState, day_13::Order, day_18::Region, AppError, BadRequest},
+  feed,
+};
+
+/// Rows are handed to `COPY` in batches of roughly this many bytes.
+const COPY_BATCH_BYTES: usize = 64 * 1024;
+/// A single record longer than this is rejected instead of being buffered forever.
+const MAX_RECORD_BYTES: usize = 1024 * 1024;
+const MAX_REPORTED_ERRORS: usize = 1000;
+const PARQUET_ROW_GROUP_SIZE: usize = 8192;
+
+pub fn get_routes(pool: PgPool) -> Router {
+  let state = MyState { pool };
+
+  Router::new()
+    .route("/orders/import", post(import_orders))
+    .route("/orders/export", get(export_orders))
+    .route("/regions/import", post(import_regions))
+    .route("/regions/export", get(export_regions))
+    .with_state(state)
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum ColumnType {
+  Int,
+  Float,
+  Text,
+}
+
+pub(crate) struct Column {
+  name: &'static str,
+  ty: ColumnType,
+  /// Whether a CSV import has to have this column in its header.
+  required: bool,
+}
+
+impl Column {
+  const fn required(name: &'static str, ty: ColumnType) -> Self {
+    Self {
+      name,
+      ty,
+      required: true,
+    }
+  }
+
+  const fn optional(name: &'static str, ty: ColumnType) -> Self {
+    Self {
+      name,
+      ty,
+      required: false,
+    }
+  }
+}
+
+/// A table that can be bulk imported and exported. Every column has to be a field of the same
+/// name on the implementing struct.
+pub(crate) trait Table: DeserializeOwned + Serialize + Send + Sized + 'static {
+  const NAME: &'static str;
+  const COLUMNS: &'static [Column];
+  /// The `kind` of the [`crate::feed::Change`] published for each inserted row.
+  const INSERTED: &'static str;
+
+  fn column_list() -> String {
+    Self::COLUMNS
+      .iter()
+      .map(|el| el.name)
+      .collect::<Vec<_>>()
+      .join(", ")
+  }
+
+  /// Checks a parsed row before it is imported.
+  fn validate(self) -> Result<Self, String> {
+    Ok(self)
+  }
+}
+
+impl Table for Order {
+  const NAME: &'static str = "orders";
+  const INSERTED: &'static str = "order_inserted";
+  const COLUMNS: &'static [Column] = &[
+    Column::required("id", ColumnType::Int),
+    Column::required("region_id", ColumnType::Int),
+    Column::required("gift_name", ColumnType::Text),
+    Column::required("quantity", ColumnType::Int),
+  ];
+}
+
+impl Table for Region {
+  const NAME: &'static str = "regions";
+  const INSERTED: &'static str = "region_inserted";
+  const COLUMNS: &'static [Column] = &[
+    Column::required("id", ColumnType::Int),
+    Column::required("name", ColumnType::Text),
+    Column::optional("parent_id", ColumnType::Int),
+    Column::optional("country_code", ColumnType::Text),
+    Column::optional("latitude", ColumnType::Float),
+    Column::optional("longitude", ColumnType::Float),
+    Column::optional("s2_cell", ColumnType::Text),
+  ];
+
+  fn validate(self) -> Result<Self, String> {
+    self.normalize()
+  }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+  Csv,
+  Ndjson,
+  Parquet,
+}
+
+impl Format {
+  const fn content_type(self) -> &'static str {
+    match self {
+      Self::Csv => "text/csv",
+      Self::Ndjson => "application/x-ndjson",
+      Self::Parquet => "application/vnd.apache.parquet",
+    }
+  }
+
+  /// Picks the format from `?format=`, falling back to the given header and then CSV.
+  fn resolve(query: &FormatQuery, headers: &HeaderMap, header: header::HeaderName) -> Self {
+    query.format.unwrap_or_else(|| {
+      let value = headers
+        .get(header)
+        .and_then(|el| el.to_str().ok())
+        .unwrap_or_default();
+
+      [Self::Ndjson, Self::Parquet]
+        .into_iter()
+        .find(|format| value.contains(format.content_type()))
+        .unwrap_or(Self::Csv)
+    })
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct FormatQuery {
+  format: Option<Format>,
+}
+
+#[derive(Serialize, Debug)]
+struct RowError {
+  line: u64,
+  error: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ImportReport {
+  format: Format,
+  received: u64,
+  inserted: u64,
+  rejected: u64,
+  errors: Vec<RowError>,
+  errors_truncated: bool,
+}
+
+async fn import_orders(
+  State(state): State<MyState>,
+  Query(query): Query<FormatQuery>,
+  headers: HeaderMap,
+  body: BodyStream,
+) -> Result<Json<ImportReport>, AppError> {
+  let format = Format::resolve(&query, &headers, header::CONTENT_TYPE);
+
+  import::<Order>(&state.pool, format, body).await
+}
+
+async fn import_regions(
+  State(state): State<MyState>,
+  Query(query): Query<FormatQuery>,
+  headers: HeaderMap,
+  body: BodyStream,
+) -> Result<Json<ImportReport>, AppError> {
+  let format = Format::resolve(&query, &headers, header::CONTENT_TYPE);
+
+  import::<Region>(&state.pool, format, body).await
+}
+
+async fn export_orders(
+  State(state): State<MyState>,
+  Query(query): Query<FormatQuery>,
+  headers: HeaderMap,
+) -> Response {
+  let format = Format::resolve(&query, &headers, header::ACCEPT);
+
+  export::<Order>(state.pool, format)
+}
+
+async fn export_regions(
+  State(state): State<MyState>,
+  Query(query): Query<FormatQuery>,
+  headers: HeaderMap,
+) -> Response {
+  let format = Format::resolve(&query, &headers, header::ACCEPT);
+
+  export::<Region>(state.pool, format)
+}
+
+/// Splits a streamed body into records. Newlines inside quoted CSV fields do not end a record.
+pub(crate) struct RecordSplitter {
+  buf: Vec<u8>,
+  start: usize,
+  scanned: usize,
+  quoted: bool,
+  csv: bool,
+}
+
+impl RecordSplitter {
+  pub(crate) const fn new(csv: bool) -> Self {
+    Self {
+      buf: Vec::new(),
+      start: 0,
+      scanned: 0,
+      quoted: false,
+      csv,
+    }
+  }
+
+  pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<(), BadRequest> {
+    let _ = self.buf.drain(..self.start);
+    self.scanned -= self.start;
+    self.start = 0;
+
+    // Everything left over at this point belongs to a single unfinished record.
+    if self.buf.len() > MAX_RECORD_BYTES {
+      return Err(BadRequest(format!(
+        "Record exceeds {MAX_RECORD_BYTES} bytes"
+      )));
+    }
+
+    self.buf.extend_from_slice(chunk);
+
+    Ok(())
+  }
+
+  pub(crate) fn next_record(&mut self) -> Option<Vec<u8>> {
+    while self.scanned < self.buf.len() {
+      let byte = self.buf[self.scanned];
+      self.scanned += 1;
+
+      if self.csv && byte == b'"' {
+        self.quoted = !self.quoted;
+      } else if byte == b'\n' && !self.quoted {
+        let record = self.buf[self.start..self.scanned].to_vec();
+        self.start = self.scanned;
+
+        return Some(record);
+      }
+    }
+
+    None
+  }
+
+  /// Returns the trailing record if the body did not end with a newline.
+  pub(crate) fn finish(&mut self) -> Option<Vec<u8>> {
+    let record = self.buf.split_off(self.start);
+
+    (!record.is_empty()).then_some(record)
+  }
+}
+
+/// Parsed rows waiting to be sent to `COPY`, along with everything needed for the report.
+struct ImportBatch {
+  format: Format,
+  csv_headers: Option<StringRecord>,
+  buf: Vec<u8>,
+  errors: Vec<RowError>,
+  parse_errors: u64,
+  received: u64,
+  line: u64,
+}
+
+impl ImportBatch {
+  const fn new(format: Format) -> Self {
+    Self {
+      format,
+      csv_headers: None,
+      buf: Vec::new(),
+      errors: Vec::new(),
+      parse_errors: 0,
+      received: 0,
+      line: 1,
+    }
+  }
+
+  #[allow(clippy::naive_bytecount)]
+  fn add<T: Table>(&mut self, record: &[u8]) -> Result<(), AppError> {
+    let line = self.line;
+    self.line += record.iter().filter(|b| **b == b'\n').count() as u64;
+
+    match self.parse::<T>(record) {
+      Ok(None) => {}
+      Ok(Some(row)) => {
+        self.received += 1;
+        self.encode::<T>(line, &serde_json::to_value(row)?);
+      }
+      Err(error) if self.format == Format::Csv && self.csv_headers.is_none() => {
+        Err(BadRequest(error))?;
+      }
+      Err(error) => {
+        self.received += 1;
+        self.parse_errors += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+          self.errors.push(RowError { line, error });
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Appends a row in `COPY`'s CSV format. Nulls are left unquoted and everything else is
+  /// quoted, so that empty strings and nulls stay distinct.
+  fn encode<T: Table>(&mut self, line: u64, row: &serde_json::Value) {
+    self.buf.extend_from_slice(line.to_string().as_bytes());
+
+    for column in T::COLUMNS {
+      self.buf.push(b',');
+
+      match &row[column.name] {
+        serde_json::Value::Null => {}
+        serde_json::Value::String(text) => {
+          self.buf.push(b'"');
+          self
+            .buf
+            .extend_from_slice(text.replace('"', "\"\"").as_bytes());
+          self.buf.push(b'"');
+        }
+        other => self.buf.extend_from_slice(other.to_string().as_bytes()),
+      }
+    }
+
+    self.buf.push(b'\n');
+  }
+
+  /// Parses one record into a row. The first CSV record is taken as the header.
+  fn parse<T: Table>(&mut self, record: &[u8]) -> Result<Option<T>, String> {
+    if record.iter().all(u8::is_ascii_whitespace) {
+      return Ok(None);
+    }
+
+    if self.format == Format::Ndjson {
+      return serde_json::from_slice::<T>(record)
+        .map_err(|e| e.to_string())
+        .and_then(T::validate)
+        .map(Some);
+    }
+
+    let parsed = csv::ReaderBuilder::new()
+      .has_headers(false)
+      .from_reader(record)
+      .records()
+      .next()
+      .unwrap_or_else(|| Ok(StringRecord::new()))
+      .map_err(|e| e.to_string())?;
+
+    let Some(headers) = &self.csv_headers else {
+      if let Some(missing) = T::COLUMNS
+        .iter()
+        .find(|column| column.required && !parsed.iter().any(|el| el.trim() == column.name))
+      {
+        return Err(format!("Header is missing column '{}'", missing.name));
+      }
+
+      self.csv_headers = Some(parsed.iter().map(str::trim).collect());
+      return Ok(None);
+    };
+
+    parsed
+      .deserialize::<T>(Some(headers))
+      .map_err(|e| e.to_string())
+      .and_then(T::validate)
+      .map(Some)
+  }
+}
+
+async fn import<T: Table>(
+  pool: &PgPool,
+  format: Format,
+  mut body: BodyStream,
+) -> Result<Json<ImportReport>, AppError> {
+  if format == Format::Parquet {
+    Err(BadRequest(
+      "Only CSV and NDJSON can be imported".to_string(),
+    ))?;
+  }
+
+  let columns = T::column_list();
+  let mut tx = pool.begin().await?;
+
+  // Rows go through a constraint-free staging table first so that duplicate ids can be reported
+  // per line instead of failing the whole COPY.
+  let _ = sqlx::query(&format!(
+    "CREATE TEMP TABLE import_staging (line BIGINT, LIKE {}) ON COMMIT DROP",
+    T::NAME
+  ))
+  .execute(&mut *tx)
+  .await?;
+
+  let mut copy = tx
+    .copy_in_raw(&format!(
+      "COPY import_staging (line, {columns}) FROM STDIN WITH (FORMAT csv)"
+    ))
+    .await?;
+
+  let mut splitter = RecordSplitter::new(format == Format::Csv);
+  let mut batch = ImportBatch::new(format);
+
+  while let Some(chunk) = body.next().await {
+    splitter.push(&chunk?)?;
+
+    while let Some(record) = splitter.next_record() {
+      batch.add::<T>(&record)?;
+    }
+
+    if batch.buf.len() >= COPY_BATCH_BYTES {
+      let _ = copy.send(mem::take(&mut batch.buf)).await?;
+    }
+  }
+
+  if let Some(record) = splitter.finish() {
+    batch.add::<T>(&record)?;
+  }
+
+  if !batch.buf.is_empty() {
+    let _ = copy.send(mem::take(&mut batch.buf)).await?;
+  }
+  let copied = copy.finish().await?;
+
+  let duplicates = sqlx::query(&format!(
+    r"SELECT line FROM import_staging s
+    WHERE EXISTS (SELECT 1 FROM {table} t WHERE t.id = s.id)
+      OR EXISTS (SELECT 1 FROM import_staging d WHERE d.id = s.id AND d.line < s.line)
+    ORDER BY line
+    LIMIT {MAX_REPORTED_ERRORS}",
+    table = T::NAME
+  ))
+  .fetch_all(&mut *tx)
+  .await?;
+
+  let mut errors = batch.errors;
+  for row in duplicates {
+    errors.push(RowError {
+      line: u64::try_from(row.try_get::<i64, _>("line")?)?,
+      error: "Duplicate id".to_string(),
+    });
+  }
+
+  // Every inserted row is also published to the change feed, in the same shape as `Change`.
+  let inserted = sqlx::query(&format!(
+    r"WITH inserted AS (
+      INSERT INTO {table} ({columns})
+      SELECT DISTINCT ON (id) {columns} FROM import_staging ORDER BY id, line
+      ON CONFLICT (id) DO NOTHING
+      RETURNING {columns}
+    )
+    SELECT COUNT(pg_notify($1, json_build_object('kind', $2::TEXT, 'row', inserted)::TEXT))
+    FROM inserted",
+    table = T::NAME
+  ))
+  .bind(feed::CHANNEL)
+  .bind(T::INSERTED)
+  .fetch_one(&mut *tx)
+  .await?
+  .try_get::<i64, _>(0)?;
+  let inserted = u64::try_from(inserted)?;
+
+  tx.commit().await?;
+
+  let rejected = batch.parse_errors + (copied - inserted);
+  errors.sort_by_key(|el| el.line);
+  errors.truncate(MAX_REPORTED_ERRORS);
+
+  Ok(Json(ImportReport {
+    format,
+    received: batch.received,
+    inserted,
+    rejected,
+    errors_truncated: rejected > errors.len() as u64,
+    errors,
+  }))
+}
+
+type Chunk = Result<Bytes, io::Error>;
+
+/// Streams the table back out. The actual work happens in a spawned task feeding a channel, so
+/// the response can start before the whole table has been read.
+fn export<T: Table>(pool: PgPool, format: Format) -> Response {
+  let (sender, receiver) = mpsc::channel::<Chunk>(16);
+
+  tokio::spawn(async move {
+    let res = match format {
+      Format::Csv => export_csv::<T>(pool, &sender).await,
+      Format::Ndjson => export_ndjson::<T>(pool, &sender).await,
+      Format::Parquet => export_parquet::<T>(pool, &sender).await,
+    };
+
+    if let Err(e) = res {
+      let _ = sender.send(Err(io::Error::other(e.to_string()))).await;
+    }
+  });
+
+  let body = stream::unfold(receiver, |mut receiver| async move {
+    receiver.recv().await.map(|chunk| (chunk, receiver))
+  });
+
+  (
+    [(header::CONTENT_TYPE, format.content_type())],
+    StreamBody::new(body),
+  )
+    .into_response()
+}
+
+fn select_all<T: Table>() -> String {
+  format!("SELECT {} FROM {} ORDER BY id", T::column_list(), T::NAME)
+}
+
+async fn export_csv<T: Table>(pool: PgPool, sender: &mpsc::Sender<Chunk>) -> anyhow::Result<()> {
+  let mut conn = pool.acquire().await?;
+  let mut rows = conn
+    .copy_out_raw(&format!(
+      "COPY ({}) TO STDOUT WITH (FORMAT csv, HEADER)",
+      select_all::<T>()
+    ))
+    .await?;
+
+  while let Some(chunk) = rows.next().await {
+    if sender.send(Ok(chunk?)).await.is_err() {
+      break;
+    }
+  }
+
+  Ok(())
+}
+
+async fn export_ndjson<T: Table>(pool: PgPool, sender: &mpsc::Sender<Chunk>) -> anyhow::Result<()> {
+  let select = select_all::<T>();
+  let mut rows = sqlx::query(&select).fetch(&pool);
+
+  while let Some(row) = rows.next().await {
+    let row = row?;
+    let mut object = Map::new();
+
+    for Column { name, ty, .. } in T::COLUMNS {
+      let value = match ty {
+        ColumnType::Int => json!(row.try_get::<Option<i32>, _>(*name)?),
+        ColumnType::Float => json!(row.try_get::<Option<f64>, _>(*name)?),
+        ColumnType::Text => json!(row.try_get::<Option<String>, _>(*name)?),
+      };
+      let _ = object.insert((*name).to_string(), value);
+    }
+
+    let mut line = serde_json::to_vec(&object)?;
+    line.push(b'\n');
+
+    if sender.send(Ok(line.into())).await.is_err() {
+      break;
+    }
+  }
+
+  Ok(())
+}
+
+async fn export_parquet<T: Table>(
+  pool: PgPool,
+  sender: &mpsc::Sender<Chunk>,
+) -> anyhow::Result<()> {
+  let fields = T::COLUMNS
+    .iter()
+    .map(|Column { name, ty, .. }| match ty {
+      ColumnType::Int => format!("OPTIONAL INT32 {name};"),
+      ColumnType::Float => format!("OPTIONAL DOUBLE {name};"),
+      ColumnType::Text => format!("OPTIONAL BYTE_ARRAY {name} (UTF8);"),
+    })
+    .collect::<String>();
+  let schema = parse_message_type(&format!("message {} {{ {fields} }}", T::NAME))?;
+
+  let mut writer = SerializedFileWriter::new(
+    Vec::new(),
+    Arc::new(schema),
+    Arc::new(WriterProperties::builder().build()),
+  )?;
+
+  let select = select_all::<T>();
+  let mut rows = sqlx::query(&select).fetch(&pool);
+  let mut group = Vec::with_capacity(PARQUET_ROW_GROUP_SIZE);
+
+  while let Some(row) = rows.next().await {
+    group.push(row?);
+
+    if group.len() == PARQUET_ROW_GROUP_SIZE {
+      write_row_group::<T>(&mut writer, &mem::take(&mut group))?;
+
+      // Whatever parquet has already written out can be sent while the next group is read.
+      let written = mem::take(writer.inner_mut());
+      if !written.is_empty() && sender.send(Ok(written.into())).await.is_err() {
+        return Ok(());
+      }
+    }
+  }
+
+  if !group.is_empty() {
+    write_row_group::<T>(&mut writer, &group)?;
+  }
+
+  let footer = writer.into_inner()?;
+  let _ = sender.send(Ok(footer.into())).await;
+
+  Ok(())
+}
+
+fn write_row_group<T: Table>(
+  writer: &mut SerializedFileWriter<Vec<u8>>,
+  rows: &[PgRow],
+) -> anyhow::Result<()> {
+  let mut group = writer.next_row_group()?;
+
+  for Column { name, ty, .. } in T::COLUMNS {
+    let mut column = group
+      .next_column()?
+      .ok_or_else(|| anyhow!("Parquet schema is missing column '{name}'"))?;
+
+    match ty {
+      ColumnType::Int => {
+        let (values, levels) = column_values::<i32>(rows, name)?;
+        let _ = column
+          .typed::<Int32Type>()
+          .write_batch(&values, Some(&levels), None)?;
+      }
+      ColumnType::Float => {
+        let (values, levels) = column_values::<f64>(rows, name)?;
+        let _ = column
+          .typed::<DoubleType>()
+          .write_batch(&values, Some(&levels), None)?;
+      }
+      ColumnType::Text => {
+        let (values, levels) = column_values::<String>(rows, name)?;
+        let values = values
+          .into_iter()
+          .map(|el| ByteArray::from(el.into_bytes()))
+          .collect::<Vec<_>>();
+        let _ = column
+          .typed::<ByteArrayType>()
+          .write_batch(&values, Some(&levels), None)?;
+      }
+    }
+
+    column.close()?;
+  }
+
+  let _ = group.close()?;
+
+  Ok(())
+}
+
+/// Reads one nullable column, returning the non-null values and parquet's definition levels.
+fn column_values<V>(rows: &[PgRow], name: &str) -> Result<(Vec<V>, Vec<i16>), sqlx::Error>
+where
+  V: for<'r> sqlx::Decode<'r, Postgres> + sqlx::Type<Postgres>,
+{
+  let values = rows
+    .iter()
+    .map(|row| row.try_get::<Option<V>, _>(name))
+    .collect::<Result<Vec<_>, _>>()?;
+  let levels = values.iter().map(|el| i16::from(el.is_some())).collect();
+
+  Ok((values.into_iter().flatten().collect(), levels))
+}
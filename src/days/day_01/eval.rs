@@ -0,0 +1,634 @@
+//! A small integer expression language for `/1/eval`.
+//!
+//! ```text
+//! pow(reduce(xor, [4, 8, 0x10]), 3) + -(1 << 4) % 7
+//! ```
+//!
+//! Operators bind like in Rust, except `**` for powers, which binds tightest and to the right.
+//! Functions are `pow(a, b)`, `abs(a)`, `min(a, b)`, `max(a, b)` and `reduce(op, list)` or
+//! `reduce(op, list, initial)`, where `op` is one of `add`, `sub`, `mul`, `div`, `mod`, `pow`,
+//! `and`, `or`, `xor`, `shl`, `shr`, `min` or `max`. Integers have no fixed size, but results
+//! larger than `max_bits` are reported as an overflow, and every node evaluated takes a step out
+//! of `max_steps`.
+
+use axum::Json;
+use num_bigint::BigInt;
+use num_traits::{Pow, Signed, ToPrimitive, Zero};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::days::{AppError, BadRequest};
+
+pub const DEFAULT_MAX_STEPS: u64 = 10_000;
+const MAX_MAX_STEPS: u64 = 1_000_000;
+
+pub const DEFAULT_MAX_BITS: u64 = 4096;
+const MAX_MAX_BITS: u64 = 1 << 20;
+
+/// Deeper nesting than this is refused before it can blow the stack.
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Rem,
+  Pow,
+  And,
+  Or,
+  Xor,
+  Shl,
+  Shr,
+  Min,
+  Max,
+}
+
+impl BinOp {
+  fn from_name(name: &str) -> Option<Self> {
+    Some(match name {
+      "add" => Self::Add,
+      "sub" => Self::Sub,
+      "mul" => Self::Mul,
+      "div" => Self::Div,
+      "mod" => Self::Rem,
+      "pow" => Self::Pow,
+      "and" => Self::And,
+      "or" => Self::Or,
+      "xor" => Self::Xor,
+      "shl" => Self::Shl,
+      "shr" => Self::Shr,
+      "min" => Self::Min,
+      "max" => Self::Max,
+      _ => return None,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+  Neg,
+  Not,
+  Abs,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+  Int(BigInt),
+  List(Vec<Self>),
+  Unary(UnaryOp, Box<Self>),
+  Binary(BinOp, Box<Self>, Box<Self>),
+  Reduce(BinOp, Box<Self>, Option<Box<Self>>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+  Int(BigInt),
+  List(Vec<Self>),
+}
+
+impl Value {
+  fn to_json(&self) -> JsonValue {
+    match self {
+      // As strings, JSON numbers lose precision long before these run out of it
+      Self::Int(el) => JsonValue::String(el.to_string()),
+      Self::List(el) => JsonValue::Array(el.iter().map(Self::to_json).collect()),
+    }
+  }
+}
+
+impl std::fmt::Display for Value {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Int(el) => write!(f, "{el}"),
+      Self::List(el) => {
+        f.write_str("[")?;
+        for (idx, el) in el.iter().enumerate() {
+          if idx > 0 {
+            f.write_str(", ")?;
+          }
+          write!(f, "{el}")?;
+        }
+        f.write_str("]")
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+  Int(BigInt),
+  Ident(String),
+  Op(&'static str),
+}
+
+/// Longest first, so `**` isn't read as two `*`.
+const OPERATORS: [&str; 16] = [
+  "**", "<<", ">>", "+", "-", "*", "/", "%", "&", "|", "^", "~", "(", ")", "[", "]",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, BadRequest> {
+  let mut tokens = Vec::new();
+  let mut rest = src;
+
+  loop {
+    rest = rest.trim_start();
+    let pos = src.len() - rest.len();
+    let Some(c) = rest.chars().next() else {
+      break;
+    };
+
+    if c.is_ascii_digit() {
+      let len = rest
+        .find(|el: char| !el.is_ascii_alphanumeric() && el != '_')
+        .unwrap_or(rest.len());
+      let literal = rest[..len].replace('_', "");
+      let (digits, radix) = match literal.get(..2) {
+        Some("0x" | "0X") => (&literal[2..], 16),
+        Some("0o" | "0O") => (&literal[2..], 8),
+        Some("0b" | "0B") => (&literal[2..], 2),
+        _ => (literal.as_str(), 10),
+      };
+      let value = BigInt::parse_bytes(digits.as_bytes(), radix)
+        .ok_or_else(|| BadRequest(format!("Invalid number {:?} at {pos}", &rest[..len])))?;
+
+      tokens.push((pos, Token::Int(value)));
+      rest = &rest[len..];
+    } else if c.is_alphabetic() || c == '_' {
+      let len = rest
+        .find(|el: char| !el.is_alphanumeric() && el != '_')
+        .unwrap_or(rest.len());
+
+      tokens.push((pos, Token::Ident(rest[..len].to_string())));
+      rest = &rest[len..];
+    } else if c == ',' {
+      tokens.push((pos, Token::Op(",")));
+      rest = &rest[1..];
+    } else {
+      let op = OPERATORS
+        .into_iter()
+        .find(|el| rest.starts_with(el))
+        .ok_or_else(|| BadRequest(format!("Unexpected {c:?} at {pos}")))?;
+
+      tokens.push((pos, Token::Op(op)));
+      rest = &rest[op.len()..];
+    }
+  }
+
+  Ok(tokens)
+}
+
+struct Parser {
+  tokens: Vec<(usize, Token)>,
+  idx: usize,
+  end: usize,
+  depth: usize,
+}
+
+/// Binary operators from the loosest to the tightest binding, all left associative.
+const LEVELS: [&[(&str, BinOp)]; 6] = [
+  &[("|", BinOp::Or)],
+  &[("^", BinOp::Xor)],
+  &[("&", BinOp::And)],
+  &[("<<", BinOp::Shl), (">>", BinOp::Shr)],
+  &[("+", BinOp::Add), ("-", BinOp::Sub)],
+  &[("*", BinOp::Mul), ("/", BinOp::Div), ("%", BinOp::Rem)],
+];
+
+impl Parser {
+  fn pos(&self) -> usize {
+    self.tokens.get(self.idx).map_or(self.end, |el| el.0)
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.idx).map(|el| &el.1)
+  }
+
+  fn next(&mut self) -> Option<Token> {
+    let token = self.tokens.get(self.idx).map(|el| el.1.clone());
+    self.idx += 1;
+    token
+  }
+
+  fn eat(&mut self, op: &str) -> bool {
+    let found = matches!(self.peek(), Some(Token::Op(el)) if *el == op);
+    if found {
+      self.idx += 1;
+    }
+
+    found
+  }
+
+  fn expect(&mut self, op: &str) -> Result<(), BadRequest> {
+    if self.eat(op) {
+      return Ok(());
+    }
+
+    Err(self.unexpected(&format!("{op:?}")))
+  }
+
+  fn unexpected(&self, expected: &str) -> BadRequest {
+    let found = match self.peek() {
+      None => "the end".to_string(),
+      Some(Token::Int(el)) => el.to_string(),
+      Some(Token::Ident(el)) => el.clone(),
+      Some(Token::Op(el)) => format!("{el:?}"),
+    };
+
+    BadRequest(format!(
+      "Expected {expected} at {}, found {found}",
+      self.pos()
+    ))
+  }
+
+  fn expr(&mut self) -> Result<Expr, BadRequest> {
+    self.enter()?;
+    let res = self.binary(0);
+    self.depth -= 1;
+
+    res
+  }
+
+  /// Evaluating and dropping the tree recurses as deep as it is nested, so that is limited.
+  fn enter(&mut self) -> Result<(), BadRequest> {
+    self.depth += 1;
+    if self.depth > MAX_DEPTH {
+      return Err(BadRequest(format!(
+        "Expression is nested deeper than {MAX_DEPTH} levels"
+      )));
+    }
+
+    Ok(())
+  }
+
+  fn binary(&mut self, level: usize) -> Result<Expr, BadRequest> {
+    let Some(ops) = LEVELS.get(level) else {
+      return self.unary();
+    };
+
+    // A chain like 1 + 2 + 3 nests to the left, one level per operator
+    let depth = self.depth;
+    let mut lhs = self.binary(level + 1)?;
+    'outer: loop {
+      for (symbol, op) in *ops {
+        if self.eat(symbol) {
+          self.enter()?;
+          let rhs = self.binary(level + 1)?;
+          lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+          continue 'outer;
+        }
+      }
+
+      self.depth = depth;
+      return Ok(lhs);
+    }
+  }
+
+  fn unary(&mut self) -> Result<Expr, BadRequest> {
+    self.enter()?;
+
+    let res = if self.eat("-") {
+      self
+        .unary()
+        .map(|el| Expr::Unary(UnaryOp::Neg, Box::new(el)))
+    } else if self.eat("~") {
+      self
+        .unary()
+        .map(|el| Expr::Unary(UnaryOp::Not, Box::new(el)))
+    } else {
+      self.power()
+    };
+    self.depth -= 1;
+
+    res
+  }
+
+  fn power(&mut self) -> Result<Expr, BadRequest> {
+    let base = self.primary()?;
+    if !self.eat("**") {
+      return Ok(base);
+    }
+
+    // Right associative, and -2 ** 2 is -(2 ** 2) while 2 ** -1 is allowed to fail later
+    let exp = self.unary()?;
+
+    Ok(Expr::Binary(BinOp::Pow, Box::new(base), Box::new(exp)))
+  }
+
+  fn list(&mut self, close: &str) -> Result<Vec<Expr>, BadRequest> {
+    let mut items = Vec::new();
+    if self.eat(close) {
+      return Ok(items);
+    }
+
+    loop {
+      items.push(self.expr()?);
+      if self.eat(close) {
+        return Ok(items);
+      }
+      self.expect(",")?;
+    }
+  }
+
+  fn primary(&mut self) -> Result<Expr, BadRequest> {
+    let pos = self.pos();
+
+    match self.next() {
+      Some(Token::Int(el)) => Ok(Expr::Int(el)),
+      Some(Token::Op("(")) => {
+        let expr = self.expr()?;
+        self.expect(")")?;
+        Ok(expr)
+      }
+      Some(Token::Op("[")) => Ok(Expr::List(self.list("]")?)),
+      Some(Token::Ident(name)) => self.call(&name, pos),
+      _ => {
+        self.idx -= 1;
+        Err(self.unexpected("a number, list or function call"))
+      }
+    }
+  }
+
+  fn call(&mut self, name: &str, pos: usize) -> Result<Expr, BadRequest> {
+    self.expect("(")?;
+
+    if name == "reduce" {
+      let op_pos = self.pos();
+      let Some(Token::Ident(op)) = self.next() else {
+        self.idx -= 1;
+        return Err(self.unexpected("an operator name like add or xor"));
+      };
+      let op = BinOp::from_name(&op)
+        .ok_or_else(|| BadRequest(format!("Unknown operator {op} at {op_pos}")))?;
+      self.expect(",")?;
+
+      let list = self.expr()?;
+      let initial = if self.eat(",") {
+        Some(Box::new(self.expr()?))
+      } else {
+        None
+      };
+      self.expect(")")?;
+
+      return Ok(Expr::Reduce(op, Box::new(list), initial));
+    }
+
+    let mut args = self.list(")")?;
+    let arity = |expected: usize| {
+      if args.len() == expected {
+        Ok(())
+      } else {
+        Err(BadRequest(format!(
+          "{name} at {pos} takes {expected} arguments, not {}",
+          args.len()
+        )))
+      }
+    };
+
+    let op = match name {
+      "abs" => {
+        arity(1)?;
+        return Ok(Expr::Unary(UnaryOp::Abs, Box::new(args.remove(0))));
+      }
+      "pow" => BinOp::Pow,
+      "min" => BinOp::Min,
+      "max" => BinOp::Max,
+      _ => return Err(BadRequest(format!("Unknown function {name} at {pos}"))),
+    };
+    arity(2)?;
+
+    let rhs = args.remove(1);
+    let lhs = args.remove(0);
+
+    Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+  }
+}
+
+pub fn parse(src: &str) -> Result<Expr, BadRequest> {
+  let mut parser = Parser {
+    tokens: tokenize(src)?,
+    idx: 0,
+    end: src.len(),
+    depth: 0,
+  };
+
+  let expr = parser.expr()?;
+  if parser.peek().is_some() {
+    return Err(parser.unexpected("an operator or the end"));
+  }
+
+  Ok(expr)
+}
+
+#[derive(Debug)]
+pub struct Evaluator {
+  pub steps: u64,
+  max_steps: u64,
+  max_bits: u64,
+}
+
+impl Default for Evaluator {
+  fn default() -> Self {
+    Self::new(DEFAULT_MAX_STEPS, DEFAULT_MAX_BITS)
+  }
+}
+
+impl Evaluator {
+  pub const fn new(max_steps: u64, max_bits: u64) -> Self {
+    Self {
+      steps: 0,
+      max_steps,
+      max_bits,
+    }
+  }
+
+  fn step(&mut self) -> Result<(), BadRequest> {
+    self.steps += 1;
+    if self.steps > self.max_steps {
+      return Err(BadRequest(format!(
+        "Evaluation took more than {} steps",
+        self.max_steps
+      )));
+    }
+
+    Ok(())
+  }
+
+  fn overflow(&self) -> BadRequest {
+    BadRequest(format!(
+      "Overflow, a result needs more than {} bits",
+      self.max_bits
+    ))
+  }
+
+  fn check(&self, value: BigInt) -> Result<BigInt, BadRequest> {
+    if value.bits() > self.max_bits {
+      return Err(self.overflow());
+    }
+
+    Ok(value)
+  }
+
+  fn int(&mut self, expr: &Expr) -> Result<BigInt, BadRequest> {
+    match self.eval(expr)? {
+      Value::Int(el) => Ok(el),
+      Value::List(_) => Err(BadRequest("Expected an integer, got a list".to_string())),
+    }
+  }
+
+  pub fn eval(&mut self, expr: &Expr) -> Result<Value, BadRequest> {
+    self.step()?;
+
+    match expr {
+      Expr::Int(el) => Ok(Value::Int(self.check(el.clone())?)),
+      Expr::List(items) => Ok(Value::List(
+        items
+          .iter()
+          .map(|el| self.eval(el))
+          .collect::<Result<_, _>>()?,
+      )),
+      Expr::Unary(op, operand) => {
+        let operand = self.int(operand)?;
+        let res = match op {
+          UnaryOp::Neg => -operand,
+          UnaryOp::Not => !operand,
+          UnaryOp::Abs => operand.abs(),
+        };
+
+        Ok(Value::Int(self.check(res)?))
+      }
+      Expr::Binary(op, lhs, rhs) => {
+        let lhs = self.int(lhs)?;
+        let rhs = self.int(rhs)?;
+
+        Ok(Value::Int(self.apply(*op, lhs, rhs)?))
+      }
+      Expr::Reduce(op, list, initial) => {
+        let Value::List(items) = self.eval(list)? else {
+          return Err(BadRequest("reduce takes a list".to_string()));
+        };
+
+        let mut acc = initial.as_ref().map(|el| self.int(el)).transpose()?;
+        for item in items {
+          self.step()?;
+          let Value::Int(item) = item else {
+            return Err(BadRequest("reduce takes a list of integers".to_string()));
+          };
+
+          acc = Some(match acc {
+            Some(acc) => self.apply(*op, acc, item)?,
+            None => item,
+          });
+        }
+
+        acc
+          .map(Value::Int)
+          .ok_or_else(|| BadRequest("reduce over an empty list needs an initial value".to_string()))
+      }
+    }
+  }
+
+  fn apply(&self, op: BinOp, lhs: BigInt, rhs: BigInt) -> Result<BigInt, BadRequest> {
+    let shift = |amount: &BigInt| {
+      if amount.is_negative() {
+        return Err(BadRequest("Cannot shift by a negative amount".to_string()));
+      }
+
+      Ok(amount.to_u64().unwrap_or(u64::MAX))
+    };
+
+    let res = match op {
+      BinOp::Add => lhs + rhs,
+      BinOp::Sub => lhs - rhs,
+      BinOp::Mul => lhs * rhs,
+      BinOp::Div | BinOp::Rem if rhs.is_zero() => {
+        return Err(BadRequest("Division by zero".to_string()))
+      }
+      // Truncating, like Rust
+      BinOp::Div => lhs / rhs,
+      BinOp::Rem => lhs % rhs,
+      BinOp::And => lhs & rhs,
+      BinOp::Or => lhs | rhs,
+      BinOp::Xor => lhs ^ rhs,
+      BinOp::Min => lhs.min(rhs),
+      BinOp::Max => lhs.max(rhs),
+      BinOp::Shl => {
+        let amount = shift(&rhs)?;
+        if lhs.is_zero() {
+          return Ok(lhs);
+        }
+        // Known to be too big before spending the memory on it
+        if lhs.bits().saturating_add(amount) > self.max_bits {
+          return Err(self.overflow());
+        }
+
+        lhs << amount
+      }
+      BinOp::Shr => {
+        // Shifting further than there are bits leaves 0 or -1 either way
+        let amount = shift(&rhs)?.min(lhs.bits() + 1);
+
+        lhs >> amount
+      }
+      BinOp::Pow => {
+        if rhs.is_negative() {
+          return Err(BadRequest(
+            "Negative exponents are not integers".to_string(),
+          ));
+        }
+        if lhs.magnitude().bits() <= 1 {
+          // 0, 1 and -1 stay small
+          let odd = rhs.bit(0);
+          return Ok(match lhs.to_i8() {
+            Some(-1) if !odd => BigInt::from(1),
+            Some(0) if rhs.is_zero() => BigInt::from(1),
+            _ => lhs,
+          });
+        }
+
+        let exp = rhs.to_u32().ok_or_else(|| self.overflow())?;
+        if (lhs.bits() - 1).saturating_mul(u64::from(exp)) >= self.max_bits {
+          return Err(self.overflow());
+        }
+
+        lhs.pow(exp)
+      }
+    };
+
+    self.check(res)
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvalRequest {
+  expr: String,
+  max_steps: Option<u64>,
+  max_bits: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvalResponse {
+  result: JsonValue,
+  steps: u64,
+}
+
+pub async fn eval(Json(req): Json<EvalRequest>) -> Result<Json<EvalResponse>, AppError> {
+  let max_steps = req.max_steps.unwrap_or(DEFAULT_MAX_STEPS);
+  let max_bits = req.max_bits.unwrap_or(DEFAULT_MAX_BITS);
+  if max_steps > MAX_MAX_STEPS {
+    return Err(BadRequest(format!("max_steps can be at most {MAX_MAX_STEPS}")).into());
+  }
+  if max_bits > MAX_MAX_BITS {
+    return Err(BadRequest(format!("max_bits can be at most {MAX_MAX_BITS}")).into());
+  }
+
+  let expr = parse(&req.expr)?;
+  let mut evaluator = Evaluator::new(max_steps, max_bits);
+  let res = evaluator.eval(&expr)?;
+
+  Ok(Json(EvalResponse {
+    result: res.to_json(),
+    steps: evaluator.steps,
+  }))
+}
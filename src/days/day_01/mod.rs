@@ -0,0 +1,44 @@
+use axum::{
+  extract::Path,
+  routing::{get, post},
+  Router,
+};
+use num_bigint::BigInt;
+
+use super::{AppError, BadRequest};
+use eval::{BinOp, Evaluator, Expr};
+
+mod eval;
+
+pub fn get_routes() -> Router {
+  Router::new()
+    // .route("/1/:num1/:num2", get(task_1))
+    .route("/1/eval", post(eval::eval))
+    .route("/1/*nums", get(task_2))
+}
+
+#[allow(clippy::unused_async, dead_code)]
+async fn task_1(Path((num1, num2)): Path<(i32, i32)>) -> String {
+  (num1 ^ num2).pow(3).to_string()
+}
+
+/// `pow(reduce(xor, [nums...]), 3)`
+async fn task_2(Path(args): Path<String>) -> Result<String, AppError> {
+  let nums = args
+    .split('/')
+    .enumerate()
+    .map(|(idx, el)| {
+      el.parse::<BigInt>()
+        .map(Expr::Int)
+        .map_err(|_| BadRequest(format!("Segment {} is not an integer: {el:?}", idx + 1)))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let expr = Expr::Binary(
+    BinOp::Pow,
+    Box::new(Expr::Reduce(BinOp::Xor, Box::new(Expr::List(nums)), None)),
+    Box::new(Expr::Int(3.into())),
+  );
+
+  Ok(Evaluator::default().eval(&expr)?.to_string())
+}
@@ -0,0 +1,291 @@
+//! Reindeer contests over any attributes the reindeer come with.
+//!
+//! A category scores every reindeer, ranks them and congratulates the winner with its template.
+//! Templates fill in `{attribute}` from the winner, plus `{name}`, `{score}` and `{category}`,
+//! and `{{`/`}}` stand for literal braces.
+
+use std::collections::{BTreeMap, HashSet};
+
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::days::{AppError, BadRequest};
+
+const DEFAULT_TEMPLATE: &str = "{name} wins {category} with a score of {score}";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reindeer {
+  pub name: String,
+  #[serde(flatten)]
+  pub attributes: Map<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Score {
+  Max {
+    attribute: String,
+  },
+  Min {
+    attribute: String,
+  },
+  /// The weighted sum of the attributes, highest wins.
+  Weighted {
+    weights: BTreeMap<String, f64>,
+  },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Category {
+  pub name: String,
+  #[serde(flatten)]
+  pub score: Score,
+  pub template: Option<String>,
+}
+
+impl Category {
+  fn max(name: &str, attribute: &str, template: &str) -> Self {
+    Self {
+      name: name.to_string(),
+      score: Score::Max {
+        attribute: attribute.to_string(),
+      },
+      template: Some(template.to_string()),
+    }
+  }
+
+  /// `None` when the reindeer doesn't have everything the category looks at.
+  fn score(&self, deer: &Reindeer) -> Result<Option<f64>, BadRequest> {
+    let value = |attribute: &str| match deer.attributes.get(attribute) {
+      None | Some(Value::Null) => Ok(None),
+      Some(value) => value.as_f64().map(Some).ok_or_else(|| {
+        BadRequest(format!(
+          "{attribute} of {} has to be a number to compete in {}",
+          deer.name, self.name
+        ))
+      }),
+    };
+
+    match &self.score {
+      Score::Max { attribute } | Score::Min { attribute } => value(attribute),
+      Score::Weighted { weights } => {
+        let mut sum = 0.0;
+        for (attribute, weight) in weights {
+          let Some(value) = value(attribute)? else {
+            return Ok(None);
+          };
+          sum += weight * value;
+        }
+
+        Ok(Some(sum))
+      }
+    }
+  }
+}
+
+/// The categories of the original challenge.
+pub fn classic_categories() -> Vec<Category> {
+  vec![
+    Category::max(
+      "fastest",
+      "speed",
+      "Speeding past the finish line with a strength of {strength} is {name}",
+    ),
+    Category::max(
+      "tallest",
+      "height",
+      "{name} is standing tall with his {antler_width} cm wide antlers",
+    ),
+    Category::max(
+      "magician",
+      "snow_magic_power",
+      "{name} could blast you away with a snow magic power of {snow_magic_power}",
+    ),
+    Category::max(
+      "consumer",
+      "cAnD13s_3ATeN-yesT3rdAy",
+      "{name} ate lots of candies, but also some {favorite_food}",
+    ),
+  ]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ranked {
+  /// Tied reindeer share a rank and the next one skips as many, like 1, 1, 3.
+  pub rank: usize,
+  pub name: String,
+  pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryResult {
+  pub category: String,
+  pub winners: Vec<String>,
+  /// The template filled in for the last of the winners to enter, who `max_by` picked in the
+  /// original challenge.
+  pub message: String,
+  pub ranking: Vec<Ranked>,
+  /// Reindeer without the attributes to be scored.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub unranked: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContestRequest {
+  pub reindeer: Vec<Reindeer>,
+  /// The classic categories when left out.
+  pub categories: Option<Vec<Category>>,
+}
+
+pub async fn run_contest(
+  Json(req): Json<ContestRequest>,
+) -> Result<Json<Vec<CategoryResult>>, AppError> {
+  let categories = req.categories.unwrap_or_else(classic_categories);
+
+  Ok(Json(run(&req.reindeer, &categories)?))
+}
+
+pub fn run(
+  reindeer: &[Reindeer],
+  categories: &[Category],
+) -> Result<Vec<CategoryResult>, BadRequest> {
+  if reindeer.is_empty() {
+    return Err(BadRequest("No reindeer entered the contest".to_string()));
+  }
+  if categories.is_empty() {
+    return Err(BadRequest(
+      "A contest needs at least one category".to_string(),
+    ));
+  }
+
+  let mut names = HashSet::new();
+  for category in categories {
+    if !names.insert(category.name.as_str()) {
+      return Err(BadRequest(format!(
+        "Category {} is defined twice",
+        category.name
+      )));
+    }
+    if matches!(&category.score, Score::Weighted { weights } if weights.is_empty()) {
+      return Err(BadRequest(format!(
+        "Weighted category {} has no weights",
+        category.name
+      )));
+    }
+  }
+
+  categories.iter().map(|el| judge(reindeer, el)).collect()
+}
+
+fn judge(reindeer: &[Reindeer], category: &Category) -> Result<CategoryResult, BadRequest> {
+  let mut scored = Vec::with_capacity(reindeer.len());
+  let mut unranked = Vec::new();
+  for deer in reindeer {
+    match category.score(deer)? {
+      Some(score) => scored.push((deer, score)),
+      None => unranked.push(deer.name.clone()),
+    }
+  }
+
+  if scored.is_empty() {
+    return Err(BadRequest(format!(
+      "No reindeer can be scored in {}",
+      category.name
+    )));
+  }
+
+  // Stable, so tied reindeer stay in the order they entered
+  match category.score {
+    Score::Min { .. } => scored.sort_by(|a, b| a.1.total_cmp(&b.1)),
+    Score::Max { .. } | Score::Weighted { .. } => scored.sort_by(|a, b| b.1.total_cmp(&a.1)),
+  }
+
+  let mut ranking = Vec::<Ranked>::with_capacity(scored.len());
+  for (idx, (deer, score)) in scored.iter().enumerate() {
+    let rank = match ranking.last() {
+      Some(prev) if prev.score.total_cmp(score).is_eq() => prev.rank,
+      _ => idx + 1,
+    };
+
+    ranking.push(Ranked {
+      rank,
+      name: deer.name.clone(),
+      score: *score,
+    });
+  }
+
+  let winners = ranking
+    .iter()
+    .take_while(|el| el.rank == 1)
+    .map(|el| el.name.clone())
+    .collect::<Vec<_>>();
+  let (winner, score) = scored[winners.len() - 1];
+  let message = render(
+    category.template.as_deref().unwrap_or(DEFAULT_TEMPLATE),
+    winner,
+    score,
+    &category.name,
+  )?;
+
+  Ok(CategoryResult {
+    category: category.name.clone(),
+    winners,
+    message,
+    ranking,
+    unranked,
+  })
+}
+
+fn render(
+  template: &str,
+  deer: &Reindeer,
+  score: f64,
+  category: &str,
+) -> Result<String, BadRequest> {
+  let mut res = String::with_capacity(template.len());
+  let mut rest = template;
+
+  while let Some(idx) = rest.find(['{', '}']) {
+    res.push_str(&rest[..idx]);
+    let brace = &rest[idx..=idx];
+    rest = &rest[idx + 1..];
+
+    if let Some(after) = rest.strip_prefix(brace) {
+      res.push_str(brace);
+      rest = after;
+      continue;
+    }
+    if brace == "}" {
+      return Err(BadRequest(format!(
+        "Unmatched }} in the template of {category}"
+      )));
+    }
+
+    let Some((key, after)) = rest.split_once('}') else {
+      return Err(BadRequest(format!(
+        "Unclosed {{ in the template of {category}"
+      )));
+    };
+    rest = after;
+
+    match key {
+      "name" => res.push_str(&deer.name),
+      "score" => res.push_str(&score.to_string()),
+      "category" => res.push_str(category),
+      key => match deer.attributes.get(key) {
+        Some(Value::String(value)) => res.push_str(value),
+        Some(value) => res.push_str(&value.to_string()),
+        None => {
+          return Err(BadRequest(format!(
+            "The template of {category} uses {key}, which {} doesn't have",
+            deer.name
+          )))
+        }
+      },
+    }
+  }
+  res.push_str(rest);
+
+  Ok(res)
+}
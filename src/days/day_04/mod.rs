@@ -0,0 +1,74 @@
+use axum::{
+  routing::{get, post},
+  Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{day_13::MyState, AppError};
+use contest::{classic_categories, Reindeer};
+
+mod contest;
+mod roster;
+
+pub fn get_routes(pool: PgPool) -> Router {
+  let state = MyState { pool };
+
+  Router::new()
+    .route("/4/strength", post(task_1))
+    .route("/4/contest", post(task_2))
+    .route("/4/contest/run", post(contest::run_contest))
+    .route(
+      "/4/reindeer",
+      get(roster::list_reindeer).post(roster::create_reindeer),
+    )
+    .route(
+      "/4/reindeer/:id",
+      get(roster::get_reindeer)
+        .put(roster::update_reindeer)
+        .delete(roster::delete_reindeer),
+    )
+    .route(
+      "/4/contests",
+      get(roster::list_contests).post(roster::hold_contest),
+    )
+    .route("/4/contests/:id", get(roster::get_contest))
+    .route("/4/leaderboard", get(roster::leaderboard))
+    .with_state(state)
+}
+
+#[derive(Deserialize, Debug)]
+struct Deer1 {
+  strength: i32,
+}
+
+#[derive(Serialize)]
+struct DeersResponse {
+  fastest: String,
+  tallest: String,
+  magician: String,
+  consumer: String,
+}
+
+async fn task_1(Json(payload): Json<Vec<Deer1>>) -> String {
+  payload
+    .iter()
+    .map(|el| el.strength)
+    .sum::<i32>()
+    .to_string()
+}
+
+async fn task_2(Json(payload): Json<Vec<Reindeer>>) -> Result<Json<DeersResponse>, AppError> {
+  let mut messages = contest::run(&payload, &classic_categories())?
+    .into_iter()
+    .map(|el| el.message);
+
+  let mut next = || messages.next().expect("One message per classic category");
+
+  Ok(Json(DeersResponse {
+    fastest: next(),
+    tallest: next(),
+    magician: next(),
+    consumer: next(),
+  }))
+}
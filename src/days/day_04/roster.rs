@@ -0,0 +1,451 @@
+//! The reindeer roster kept in the database, and the contests held with it.
+
+use std::collections::BTreeMap;
+
+use axum::{
+  extract::{Path, Query, State},
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::contest::{self, classic_categories, Category, CategoryResult, Reindeer};
+use crate::{
+  days::{day_13::MyState, AppError, BadRequest},
+  pagination::{Page, Pagination},
+};
+
+#[derive(Debug, Serialize)]
+pub struct StoredReindeer {
+  id: i64,
+  name: String,
+  attributes: Map<String, Value>,
+  created_at: String,
+  updated_at: String,
+}
+
+impl StoredReindeer {
+  fn new(
+    id: i64,
+    name: String,
+    attributes: Value,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+  ) -> Self {
+    Self {
+      id,
+      name,
+      attributes: match attributes {
+        Value::Object(el) => el,
+        _ => Map::new(),
+      },
+      created_at: created_at.to_rfc3339(),
+      updated_at: updated_at.to_rfc3339(),
+    }
+  }
+
+  fn into_reindeer(self) -> Reindeer {
+    Reindeer {
+      name: self.name,
+      attributes: self.attributes,
+    }
+  }
+}
+
+fn validate(deer: &Reindeer) -> Result<(), BadRequest> {
+  if deer.name.trim().is_empty() {
+    return Err(BadRequest("Reindeer need a name".to_string()));
+  }
+
+  Ok(())
+}
+
+/// Names are unique on the roster.
+fn name_taken(e: sqlx::Error, name: &str) -> AppError {
+  match e.as_database_error() {
+    Some(db) if db.is_unique_violation() => {
+      BadRequest(format!("There already is a reindeer called {name}")).into()
+    }
+    _ => e.into(),
+  }
+}
+
+pub async fn list_reindeer(
+  pagination: Pagination,
+  State(state): State<MyState>,
+) -> Result<Page<StoredReindeer>, AppError> {
+  Ok(pagination.page(fetch_roster(&state).await?))
+}
+
+async fn fetch_roster(state: &MyState) -> Result<Vec<StoredReindeer>, AppError> {
+  let res =
+    sqlx::query!("SELECT id, name, attributes, created_at, updated_at FROM reindeer ORDER BY id")
+      .fetch_all(&state.pool)
+      .await?
+      .into_iter()
+      .map(|el| StoredReindeer::new(el.id, el.name, el.attributes, el.created_at, el.updated_at))
+      .collect();
+
+  Ok(res)
+}
+
+pub async fn create_reindeer(
+  State(state): State<MyState>,
+  Json(deer): Json<Reindeer>,
+) -> Result<impl IntoResponse, AppError> {
+  validate(&deer)?;
+
+  let el = sqlx::query!(
+    r"INSERT INTO reindeer (name, attributes) VALUES ($1, $2)
+      RETURNING id, name, attributes, created_at, updated_at",
+    deer.name,
+    Value::Object(deer.attributes),
+  )
+  .fetch_one(&state.pool)
+  .await
+  .map_err(|e| name_taken(e, &deer.name))?;
+
+  Ok((
+    StatusCode::CREATED,
+    Json(StoredReindeer::new(
+      el.id,
+      el.name,
+      el.attributes,
+      el.created_at,
+      el.updated_at,
+    )),
+  ))
+}
+
+pub async fn get_reindeer(
+  Path(id): Path<i64>,
+  State(state): State<MyState>,
+) -> Result<Response, AppError> {
+  let res = sqlx::query!(
+    "SELECT id, name, attributes, created_at, updated_at FROM reindeer WHERE id = $1",
+    id
+  )
+  .fetch_optional(&state.pool)
+  .await?;
+
+  Ok(res.map_or_else(
+    || StatusCode::NOT_FOUND.into_response(),
+    |el| {
+      Json(StoredReindeer::new(
+        el.id,
+        el.name,
+        el.attributes,
+        el.created_at,
+        el.updated_at,
+      ))
+      .into_response()
+    },
+  ))
+}
+
+/// Replaces the name and all attributes of the reindeer.
+pub async fn update_reindeer(
+  Path(id): Path<i64>,
+  State(state): State<MyState>,
+  Json(deer): Json<Reindeer>,
+) -> Result<Response, AppError> {
+  validate(&deer)?;
+
+  let res = sqlx::query!(
+    r"UPDATE reindeer SET name = $2, attributes = $3, updated_at = now() WHERE id = $1
+      RETURNING id, name, attributes, created_at, updated_at",
+    id,
+    deer.name,
+    Value::Object(deer.attributes),
+  )
+  .fetch_optional(&state.pool)
+  .await
+  .map_err(|e| name_taken(e, &deer.name))?;
+
+  Ok(res.map_or_else(
+    || StatusCode::NOT_FOUND.into_response(),
+    |el| {
+      Json(StoredReindeer::new(
+        el.id,
+        el.name,
+        el.attributes,
+        el.created_at,
+        el.updated_at,
+      ))
+      .into_response()
+    },
+  ))
+}
+
+pub async fn delete_reindeer(
+  Path(id): Path<i64>,
+  State(state): State<MyState>,
+) -> Result<StatusCode, AppError> {
+  let res = sqlx::query!("DELETE FROM reindeer WHERE id = $1", id)
+    .execute(&state.pool)
+    .await?;
+
+  if res.rows_affected() == 0 {
+    return Ok(StatusCode::NOT_FOUND);
+  }
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Bounds {
+  min: Option<f64>,
+  max: Option<f64>,
+}
+
+/// Which reindeer on the roster take part, everyone by default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RosterFilter {
+  names: Option<Vec<String>>,
+  /// Attributes that have to have exactly these values.
+  #[serde(default)]
+  equals: Map<String, Value>,
+  /// Numeric attributes that have to be within these bounds, inclusive.
+  #[serde(default)]
+  ranges: BTreeMap<String, Bounds>,
+}
+
+impl RosterFilter {
+  fn matches(&self, deer: &Reindeer) -> bool {
+    self.names.as_ref().is_none_or(|el| el.contains(&deer.name))
+      && self
+        .equals
+        .iter()
+        .all(|(key, value)| deer.attributes.get(key) == Some(value))
+      && self.ranges.iter().all(|(key, bounds)| {
+        deer
+          .attributes
+          .get(key)
+          .and_then(Value::as_f64)
+          .is_some_and(|el| {
+            bounds.min.is_none_or(|min| el >= min) && bounds.max.is_none_or(|max| el <= max)
+          })
+      })
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RosterContest {
+  /// The classic categories when left out.
+  categories: Option<Vec<Category>>,
+  #[serde(default)]
+  filter: RosterFilter,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StoredContest {
+  id: i64,
+  held_at: String,
+  entrants: Vec<String>,
+  categories: Vec<Category>,
+  results: Vec<CategoryResult>,
+}
+
+impl StoredContest {
+  fn new(
+    id: i64,
+    held_at: DateTime<Utc>,
+    entrants: Vec<String>,
+    categories: Value,
+    results: Value,
+  ) -> Result<Self, AppError> {
+    Ok(Self {
+      id,
+      held_at: held_at.to_rfc3339(),
+      entrants,
+      categories: serde_json::from_value(categories)?,
+      results: serde_json::from_value(results)?,
+    })
+  }
+}
+
+/// Runs a contest with the roster and keeps the results.
+pub async fn hold_contest(
+  State(state): State<MyState>,
+  Json(req): Json<RosterContest>,
+) -> Result<Json<StoredContest>, AppError> {
+  let categories = req.categories.unwrap_or_else(classic_categories);
+  let reindeer = fetch_roster(&state)
+    .await?
+    .into_iter()
+    .map(StoredReindeer::into_reindeer)
+    .filter(|el| req.filter.matches(el))
+    .collect::<Vec<_>>();
+
+  let results = contest::run(&reindeer, &categories)?;
+  let entrants = reindeer.into_iter().map(|el| el.name).collect::<Vec<_>>();
+
+  let mut tx = state.pool.begin().await?;
+
+  let el = sqlx::query!(
+    r"INSERT INTO contests (entrants, categories, results) VALUES ($1, $2, $3)
+      RETURNING id, held_at",
+    &entrants,
+    serde_json::to_value(&categories)?,
+    serde_json::to_value(&results)?,
+  )
+  .fetch_one(&mut *tx)
+  .await?;
+
+  for result in &results {
+    for winner in &result.winners {
+      let _ = sqlx::query!(
+        "INSERT INTO contest_winners (contest_id, category, reindeer) VALUES ($1, $2, $3)",
+        el.id,
+        result.category,
+        winner,
+      )
+      .execute(&mut *tx)
+      .await?;
+    }
+  }
+
+  tx.commit().await?;
+
+  Ok(Json(StoredContest {
+    id: el.id,
+    held_at: el.held_at.to_rfc3339(),
+    entrants,
+    categories,
+    results,
+  }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Period {
+  since: Option<String>,
+  until: Option<String>,
+  /// Only count wins in this category.
+  category: Option<String>,
+}
+
+type Bound = Option<DateTime<Utc>>;
+
+impl Period {
+  fn bounds(&self) -> Result<(Bound, Bound), BadRequest> {
+    let parse = |name: &str, value: &Option<String>| {
+      value
+        .as_deref()
+        .map(|el| {
+          DateTime::parse_from_rfc3339(el)
+            .map(|el| el.with_timezone(&Utc))
+            .map_err(|e| BadRequest(format!("{name} is not an RFC 3339 timestamp: {e}")))
+        })
+        .transpose()
+    };
+
+    Ok((parse("since", &self.since)?, parse("until", &self.until)?))
+  }
+}
+
+/// Contests held in the period, newest first.
+pub async fn list_contests(
+  Query(period): Query<Period>,
+  pagination: Pagination,
+  State(state): State<MyState>,
+) -> Result<Page<StoredContest>, AppError> {
+  let (since, until) = period.bounds()?;
+
+  let res = sqlx::query!(
+    r"SELECT id, held_at, entrants, categories, results
+      FROM contests
+      WHERE ($1::timestamptz IS NULL OR held_at >= $1)
+        AND ($2::timestamptz IS NULL OR held_at < $2)
+      ORDER BY held_at DESC, id DESC",
+    since,
+    until,
+  )
+  .fetch_all(&state.pool)
+  .await?
+  .into_iter()
+  .map(|el| StoredContest::new(el.id, el.held_at, el.entrants, el.categories, el.results))
+  .collect::<Result<Vec<_>, _>>()?;
+
+  Ok(pagination.page(res))
+}
+
+pub async fn get_contest(
+  Path(id): Path<i64>,
+  State(state): State<MyState>,
+) -> Result<Response, AppError> {
+  let res = sqlx::query!(
+    "SELECT id, held_at, entrants, categories, results FROM contests WHERE id = $1",
+    id
+  )
+  .fetch_optional(&state.pool)
+  .await?;
+
+  let Some(el) = res else {
+    return Ok(StatusCode::NOT_FOUND.into_response());
+  };
+
+  Ok(
+    Json(StoredContest::new(
+      el.id,
+      el.held_at,
+      el.entrants,
+      el.categories,
+      el.results,
+    )?)
+    .into_response(),
+  )
+}
+
+#[derive(Debug, Serialize)]
+pub struct Standing {
+  /// Reindeer with as many wins share a rank.
+  rank: usize,
+  name: String,
+  wins: i64,
+}
+
+/// Who won most often in the period. Every category won counts, ties included.
+pub async fn leaderboard(
+  Query(period): Query<Period>,
+  pagination: Pagination,
+  State(state): State<MyState>,
+) -> Result<Page<Standing>, AppError> {
+  let (since, until) = period.bounds()?;
+
+  let rows = sqlx::query!(
+    r#"SELECT w.reindeer AS "name!", COUNT(*) AS "wins!"
+      FROM contest_winners w
+        JOIN contests c ON c.id = w.contest_id
+      WHERE ($1::timestamptz IS NULL OR c.held_at >= $1)
+        AND ($2::timestamptz IS NULL OR c.held_at < $2)
+        AND ($3::text IS NULL OR w.category = $3)
+      GROUP BY w.reindeer
+      ORDER BY COUNT(*) DESC, w.reindeer"#,
+    since,
+    until,
+    period.category,
+  )
+  .fetch_all(&state.pool)
+  .await?;
+
+  let mut standings = Vec::<Standing>::with_capacity(rows.len());
+  for (idx, el) in rows.into_iter().enumerate() {
+    let rank = match standings.last() {
+      Some(prev) if prev.wins == el.wins => prev.rank,
+      _ => idx + 1,
+    };
+
+    standings.push(Standing {
+      rank,
+      name: el.name,
+      wins: el.wins,
+    });
+  }
+
+  Ok(pagination.page(standings))
+}
@@ -0,0 +1,332 @@
+//! Counting any number of patterns in one text.
+//!
+//! Literal patterns are all found in a single pass with Aho-Corasick, regexes get a pass each.
+//! Every match is a candidate that the pattern's options may still throw away, in this order:
+//! whole words, what precedes it, and finally overlap with the previously counted match.
+
+use std::collections::HashMap;
+
+use aho_corasick::AhoCorasick;
+use axum::Json;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::days::{AppError, BadRequest};
+
+/// The body can be a few megabytes of text.
+pub const MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+const MAX_PATTERNS: usize = 256;
+
+/// More offsets than this per pattern are counted but not listed.
+const MAX_OFFSETS: usize = 10_000;
+
+/// A regex looked for again after every match may go through its matches this many times the
+/// length of the text, plus a megabyte, so long matches like `a.*` can't make it quadratic.
+const RESCAN_FACTOR: usize = 16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+  #[default]
+  Byte,
+  Char,
+}
+
+const fn yes() -> bool {
+  true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PatternSpec {
+  /// What the count is reported as, the pattern itself by default.
+  pub name: Option<String>,
+  pub pattern: String,
+  #[serde(default)]
+  pub regex: bool,
+  #[serde(default = "yes")]
+  pub case_sensitive: bool,
+  #[serde(default)]
+  pub overlapping: bool,
+  #[serde(default)]
+  pub whole_word: bool,
+  /// Matches right after this literal text don't count, like a shelf with an elf on it.
+  pub not_preceded_by: Option<String>,
+}
+
+impl PatternSpec {
+  pub fn literal(name: &str, pattern: &str) -> Self {
+    Self {
+      name: Some(name.to_string()),
+      pattern: pattern.to_string(),
+      regex: false,
+      case_sensitive: true,
+      overlapping: false,
+      whole_word: false,
+      not_preceded_by: None,
+    }
+  }
+
+  /// Whether a candidate match has to be checked against the text around it.
+  const fn is_filtered(&self) -> bool {
+    self.whole_word || self.not_preceded_by.is_some()
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountRequest {
+  pub text: String,
+  pub patterns: Vec<PatternSpec>,
+  #[serde(default)]
+  pub unit: Unit,
+  /// Whether to list where the matches are or only count them.
+  #[serde(default = "yes")]
+  pub offsets: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PatternCount {
+  pub name: String,
+  pub count: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub offsets: Option<Vec<Span>>,
+  /// Whether there were more matches than offsets listed.
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
+  pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountResponse {
+  pub unit: &'static str,
+  pub results: Vec<PatternCount>,
+}
+
+pub async fn count(Json(req): Json<CountRequest>) -> Result<Json<CountResponse>, AppError> {
+  let unit = req.unit;
+  // Megabytes of text keep a thread busy for a while
+  let results = tokio::task::spawn_blocking(move || count_patterns(&req)).await??;
+
+  Ok(Json(CountResponse {
+    unit: match unit {
+      Unit::Byte => "byte",
+      Unit::Char => "char",
+    },
+    results,
+  }))
+}
+
+struct Counter<'a> {
+  spec: &'a PatternSpec,
+  count: usize,
+  last_end: Option<usize>,
+  offsets: Option<Vec<Span>>,
+  truncated: bool,
+}
+
+impl<'a> Counter<'a> {
+  const fn new(spec: &'a PatternSpec, offsets: bool) -> Self {
+    Self {
+      spec,
+      count: 0,
+      last_end: None,
+      offsets: if offsets { Some(Vec::new()) } else { None },
+      truncated: false,
+    }
+  }
+
+  /// Takes candidate matches in the order they start.
+  fn push(&mut self, text: &str, start: usize, end: usize) {
+    let spec = self.spec;
+
+    if spec.whole_word && !is_whole_word(text, start, end) {
+      return;
+    }
+    if let Some(prefix) = &spec.not_preceded_by {
+      if is_preceded_by(&text[..start], prefix, spec.case_sensitive) {
+        return;
+      }
+    }
+    if !spec.overlapping && self.last_end.is_some_and(|el| start < el) {
+      return;
+    }
+
+    self.count += 1;
+    self.last_end = Some(end);
+
+    if let Some(offsets) = &mut self.offsets {
+      if offsets.len() < MAX_OFFSETS {
+        offsets.push(Span { start, end });
+      } else {
+        self.truncated = true;
+      }
+    }
+  }
+
+  fn finish(self) -> PatternCount {
+    PatternCount {
+      name: self
+        .spec
+        .name
+        .clone()
+        .unwrap_or_else(|| self.spec.pattern.clone()),
+      count: self.count,
+      offsets: self.offsets,
+      truncated: self.truncated,
+    }
+  }
+}
+
+fn is_word_char(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+fn is_whole_word(text: &str, start: usize, end: usize) -> bool {
+  !text[..start].chars().next_back().is_some_and(is_word_char)
+    && !text[end..].chars().next().is_some_and(is_word_char)
+}
+
+fn is_preceded_by(before: &str, prefix: &str, case_sensitive: bool) -> bool {
+  if case_sensitive {
+    return before.ends_with(prefix);
+  }
+
+  let mut before = before.chars().rev();
+  prefix.chars().rev().all(|expected| {
+    before
+      .next()
+      .is_some_and(|el| el.to_lowercase().eq(expected.to_lowercase()))
+  })
+}
+
+fn compile(spec: &PatternSpec) -> Result<Regex, BadRequest> {
+  let source = if spec.regex {
+    spec.pattern.clone()
+  } else {
+    regex::escape(&spec.pattern)
+  };
+
+  RegexBuilder::new(&source)
+    .case_insensitive(!spec.case_sensitive)
+    .build()
+    .map_err(|e| BadRequest(format!("Invalid regex {:?}: {e}", spec.pattern)))
+}
+
+pub fn count_patterns(req: &CountRequest) -> Result<Vec<PatternCount>, BadRequest> {
+  if req.patterns.len() > MAX_PATTERNS {
+    return Err(BadRequest(format!(
+      "At most {MAX_PATTERNS} patterns can be counted at once"
+    )));
+  }
+  if let Some(idx) = req.patterns.iter().position(|el| el.pattern.is_empty()) {
+    return Err(BadRequest(format!("Pattern {idx} is empty")));
+  }
+
+  let text = req.text.as_str();
+  let mut counters = req
+    .patterns
+    .iter()
+    .map(|el| Counter::new(el, req.offsets))
+    .collect::<Vec<_>>();
+
+  // Aho-Corasick only folds ASCII, other case-insensitive literals go through the regex engine
+  let mut literals = [Vec::new(), Vec::new()];
+  let mut regexes = Vec::new();
+  for (idx, spec) in req.patterns.iter().enumerate() {
+    if !spec.regex && (spec.case_sensitive || spec.pattern.is_ascii()) {
+      literals[usize::from(!spec.case_sensitive)].push(idx);
+    } else {
+      regexes.push((idx, compile(spec)?));
+    }
+  }
+
+  for (case_insensitive, indices) in literals.iter().enumerate() {
+    if indices.is_empty() {
+      continue;
+    }
+
+    let automaton = AhoCorasick::builder()
+      .ascii_case_insensitive(case_insensitive == 1)
+      .build(indices.iter().map(|&el| &req.patterns[el].pattern))
+      .map_err(|e| BadRequest(format!("Too many or too long patterns: {e}")))?;
+
+    // Every match of a pattern has the same length, so they come in the order they start
+    for found in automaton.find_overlapping_iter(text) {
+      counters[indices[found.pattern().as_usize()]].push(text, found.start(), found.end());
+    }
+  }
+
+  for (idx, regex) in regexes {
+    let counter = &mut counters[idx];
+    if !counter.spec.overlapping && !counter.spec.is_filtered() {
+      for found in regex.find_iter(text) {
+        counter.push(text, found.start(), found.end());
+      }
+      continue;
+    }
+
+    // Filtered matches must not hide the ones overlapping them, so look again after every start
+    let mut pos = 0;
+    let mut scanned = 0_usize;
+    let max_scanned = text.len().saturating_mul(RESCAN_FACTOR) + 1024 * 1024;
+    while let Some(found) = regex.find_at(text, pos) {
+      scanned = scanned.saturating_add(found.end() - pos);
+      if scanned > max_scanned {
+        return Err(BadRequest(format!(
+          "Pattern {idx} matches too much of the text to be counted overlapping or filtered"
+        )));
+      }
+      counter.push(text, found.start(), found.end());
+
+      let Some(c) = text[found.start()..].chars().next() else {
+        break;
+      };
+      pos = found.start() + c.len_utf8();
+    }
+  }
+
+  let mut results = counters
+    .into_iter()
+    .map(Counter::finish)
+    .collect::<Vec<_>>();
+  if req.unit == Unit::Char {
+    to_char_offsets(text, &mut results);
+  }
+
+  Ok(results)
+}
+
+/// Turns byte offsets into char offsets, walking the text once.
+fn to_char_offsets(text: &str, results: &mut [PatternCount]) {
+  let mut positions = results
+    .iter()
+    .filter_map(|el| el.offsets.as_ref())
+    .flatten()
+    .flat_map(|el| [el.start, el.end])
+    .collect::<Vec<_>>();
+  positions.sort_unstable();
+  positions.dedup();
+
+  let mut chars = HashMap::with_capacity(positions.len());
+  let (mut byte, mut char) = (0, 0);
+  for pos in positions {
+    char += text[byte..pos].chars().count();
+    byte = pos;
+    let _ = chars.insert(pos, char);
+  }
+
+  for span in results
+    .iter_mut()
+    .filter_map(|el| el.offsets.as_mut())
+    .flatten()
+  {
+    span.start = chars[&span.start];
+    span.end = chars[&span.end];
+  }
+}
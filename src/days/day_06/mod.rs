@@ -0,0 +1,54 @@
+use axum::{extract::DefaultBodyLimit, routing::post, Json, Router};
+use serde::Serialize;
+
+use count::{count_patterns, CountRequest, PatternSpec, Unit};
+
+mod count;
+
+pub fn get_routes() -> Router {
+  Router::new().route("/6", post(task)).route(
+    "/6/count",
+    post(count::count).layer(DefaultBodyLimit::max(count::MAX_BODY_BYTES)),
+  )
+}
+
+#[derive(Serialize, Debug)]
+struct ElfCount {
+  #[serde(rename(serialize = "elf"))]
+  count: usize,
+  #[serde(rename(serialize = "elf on a shelf"))]
+  on_shelf: usize,
+  #[serde(rename(serialize = "shelf with no elf on it"))]
+  no_shelf: usize,
+}
+
+async fn task(payload: String) -> Json<ElfCount> {
+  let req = CountRequest {
+    text: payload,
+    patterns: vec![
+      PatternSpec::literal("elf", "elf"),
+      PatternSpec {
+        overlapping: true,
+        ..PatternSpec::literal("elf on a shelf", "elf on a shelf")
+      },
+      PatternSpec {
+        not_preceded_by: Some("elf on a ".to_string()),
+        ..PatternSpec::literal("shelf with no elf on it", "shelf")
+      },
+    ],
+    unit: Unit::Byte,
+    offsets: false,
+  };
+
+  let counts = count_patterns(&req)
+    .expect("The fixed patterns are valid")
+    .into_iter()
+    .map(|el| el.count)
+    .collect::<Vec<_>>();
+
+  Json(ElfCount {
+    count: counts[0],
+    on_shelf: counts[1],
+    no_shelf: counts[2],
+  })
+}
@@ -0,0 +1,280 @@
+//! Cookies for the day 7 endpoints, plain base64 like the original challenge or protected with
+//! one of the keys in `COOKIES`.
+//!
+//! Signed cookies look like `s.<key id>.<payload>.<mac>` and encrypted ones like
+//! `e.<key id>.<nonce and ciphertext>`, everything in unpadded URL-safe base64. Both are bound to
+//! the cookie name so one can't be passed off as another. The first key protects new cookies and
+//! the others are only used to read old ones, so keys can be rotated by putting a new one first.
+
+use aes_gcm::{
+  aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+  Aes256Gcm, Nonce,
+};
+use axum::{
+  async_trait,
+  extract::FromRequestParts,
+  http::{header, request::Parts},
+};
+use base64::{
+  engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+  Engine as _,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use tracing::warn;
+
+use crate::days::BadRequest;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Every cookie sent with the request, in order.
+#[derive(Debug, Default)]
+pub struct CookieJar(Vec<(String, String)>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CookieJar {
+  type Rejection = std::convert::Infallible;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    Ok(Self::from_headers(&parts.headers))
+  }
+}
+
+impl CookieJar {
+  pub fn from_headers(headers: &axum::http::HeaderMap) -> Self {
+    let cookies = headers
+      .get_all(header::COOKIE)
+      .iter()
+      .filter_map(|el| el.to_str().ok())
+      .flat_map(|el| el.split(';'))
+      .filter_map(|el| {
+        let (name, value) = el.split_once('=')?;
+        let name = name.trim();
+        let value = value.trim();
+        // Quotes are not part of the value
+        let value = value
+          .strip_prefix('"')
+          .and_then(|el| el.strip_suffix('"'))
+          .unwrap_or(value);
+
+        (!name.is_empty()).then(|| (name.to_string(), value.to_string()))
+      })
+      .collect();
+
+    Self(cookies)
+  }
+
+  /// The first cookie with the name.
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self
+      .0
+      .iter()
+      .find(|(el, _)| el == name)
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Protection {
+  Plain,
+  Signed,
+  Encrypted,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyConfig {
+  id: String,
+  secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CookiesConfig {
+  keys: Vec<KeyConfig>,
+  /// How the pantry cookie set by `/7/bake` is protected.
+  #[serde(default = "default_pantry")]
+  pantry: Protection,
+}
+
+const fn default_pantry() -> Protection {
+  Protection::Encrypted
+}
+
+struct CookieKey {
+  id: String,
+  mac: Vec<u8>,
+  cipher: Aes256Gcm,
+}
+
+impl std::fmt::Debug for CookieKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("CookieKey")
+      .field("id", &self.id)
+      .finish_non_exhaustive()
+  }
+}
+
+impl CookieKey {
+  /// Signing and encrypting use different keys derived from the secret.
+  fn new(id: String, secret: &[u8]) -> Self {
+    let derive = |purpose: &[u8]| {
+      let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC takes keys of any size");
+      mac.update(purpose);
+      mac.finalize().into_bytes()
+    };
+
+    Self {
+      id,
+      mac: derive(b"day 7 cookie signing").to_vec(),
+      cipher: Aes256Gcm::new(&derive(b"day 7 cookie encryption")),
+    }
+  }
+
+  fn mac(&self, name: &str, signed: &str) -> HmacSha256 {
+    let mut mac =
+      <HmacSha256 as Mac>::new_from_slice(&self.mac).expect("HMAC takes keys of any size");
+    mac.update(name.as_bytes());
+    mac.update(b"=");
+    mac.update(signed.as_bytes());
+
+    mac
+  }
+}
+
+#[derive(Debug)]
+pub struct CookieKeys {
+  /// The current key first.
+  keys: Vec<CookieKey>,
+  pub pantry: Protection,
+}
+
+impl CookieKeys {
+  /// Reads the keys as JSON from `COOKIES`, like
+  /// `{"keys": [{"id": "2", "secret": "..."}], "pantry": "signed"}`.
+  pub fn from_env() -> Self {
+    let config = std::env::var("COOKIES")
+      .ok()
+      .and_then(|el| {
+        serde_json::from_str::<CookiesConfig>(&el)
+          .map_err(|e| warn!("Ignoring invalid COOKIES: {:?}", e))
+          .ok()
+      })
+      .filter(|el| {
+        let valid = !el.keys.is_empty() && el.keys.iter().all(|el| !el.id.contains('.'));
+        if !valid {
+          warn!("Ignoring COOKIES without keys or with a key id containing a dot");
+        }
+
+        valid
+      });
+
+    let Some(config) = config else {
+      warn!("COOKIES is not set, protected cookies will only be readable until a restart");
+      let secret = format!("{}{}", ulid::Ulid::new(), ulid::Ulid::new());
+
+      return Self {
+        keys: vec![CookieKey::new("default".to_string(), secret.as_bytes())],
+        pantry: default_pantry(),
+      };
+    };
+
+    Self {
+      keys: config
+        .keys
+        .into_iter()
+        .map(|el| CookieKey::new(el.id, el.secret.as_bytes()))
+        .collect(),
+      pantry: config.pantry,
+    }
+  }
+
+  fn key(&self, id: &str) -> Result<&CookieKey, BadRequest> {
+    self
+      .keys
+      .iter()
+      .find(|el| el.id == id)
+      .ok_or_else(|| BadRequest(format!("Cookie was protected with unknown key {id}")))
+  }
+
+  /// Decodes the cookie and tells how it was protected.
+  pub fn open(&self, name: &str, value: &str) -> Result<(Vec<u8>, Protection), BadRequest> {
+    let invalid = || BadRequest(format!("Invalid {name} cookie"));
+
+    let parts = value.split('.').collect::<Vec<_>>();
+    match parts.as_slice() {
+      ["s", id, payload, mac] => {
+        let signed = &value[..value.len() - mac.len() - 1];
+        let mac = URL_SAFE_NO_PAD.decode(mac).map_err(|_| invalid())?;
+        self
+          .key(id)?
+          .mac(name, signed)
+          .verify_slice(&mac)
+          .map_err(|_| BadRequest(format!("Forged {name} cookie")))?;
+
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        Ok((payload, Protection::Signed))
+      }
+      ["e", id, sealed] => {
+        let sealed = URL_SAFE_NO_PAD.decode(sealed).map_err(|_| invalid())?;
+        if sealed.len() < 12 {
+          return Err(invalid());
+        }
+        let (nonce, ciphertext) = sealed.split_at(12);
+
+        let payload = self
+          .key(id)?
+          .cipher
+          .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+              msg: ciphertext,
+              aad: name.as_bytes(),
+            },
+          )
+          .map_err(|_| BadRequest(format!("Forged {name} cookie")))?;
+
+        Ok((payload, Protection::Encrypted))
+      }
+      [_] => Ok((
+        STANDARD.decode(value).map_err(|_| invalid())?,
+        Protection::Plain,
+      )),
+      _ => Err(invalid()),
+    }
+  }
+
+  /// Encodes the payload as the value of a cookie with the name.
+  pub fn seal(&self, name: &str, payload: &[u8], protection: Protection) -> String {
+    let key = &self.keys[0];
+
+    match protection {
+      Protection::Plain => STANDARD.encode(payload),
+      Protection::Signed => {
+        let signed = format!("s.{}.{}", key.id, URL_SAFE_NO_PAD.encode(payload));
+        let mac = key.mac(name, &signed).finalize().into_bytes();
+
+        format!("{signed}.{}", URL_SAFE_NO_PAD.encode(mac))
+      }
+      Protection::Encrypted => {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = key
+          .cipher
+          .encrypt(
+            &nonce,
+            Payload {
+              msg: payload,
+              aad: name.as_bytes(),
+            },
+          )
+          .expect("AES-GCM encrypts anything that fits in memory");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+
+        format!("e.{}.{}", key.id, URL_SAFE_NO_PAD.encode(sealed))
+      }
+    }
+  }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+  extract::State,
+  http::header,
+  response::IntoResponse,
+  routing::{get, post},
+  Json, Router,
+};
+use serde_json::{json, Value};
+
+use super::{AppError, BadRequest};
+use cookie::{CookieJar, CookieKeys, Protection};
+
+mod cookie;
+mod recipe;
+
+pub fn get_routes() -> Router {
+  Router::new()
+    .route("/7/decode", get(task_1))
+    .route("/7/bake", get(task_3))
+    .with_state(Arc::new(CookieKeys::from_env()))
+    .route("/7/plan", post(recipe::plan))
+}
+
+/// Reads the cookie with the name, however it was protected.
+fn read_cookie(
+  keys: &CookieKeys,
+  jar: &CookieJar,
+  name: &str,
+) -> Result<(Vec<u8>, Protection), BadRequest> {
+  let value = jar
+    .get(name)
+    .ok_or_else(|| BadRequest(format!("Missing {name} cookie")))?;
+
+  keys.open(name, value)
+}
+
+async fn task_1(State(keys): State<Arc<CookieKeys>>, jar: CookieJar) -> Result<String, AppError> {
+  let (decoded, _) = read_cookie(&keys, &jar, "recipe")?;
+
+  Ok(String::from_utf8(decoded).map_err(|_| BadRequest("Recipe is not UTF-8".to_string()))?)
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CookieData {
+  recipe: HashMap<String, u64>,
+  pantry: HashMap<String, u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Ingredients {
+  flour: u32,
+  sugar: u32,
+  butter: u32,
+  #[serde(rename(deserialize = "baking powder"))]
+  baking_powder: u32,
+  #[serde(rename(deserialize = "chocolate chips"))]
+  chocolate_chips: u32,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct CookieResponse {
+  cookies: u32,
+  pantry: Ingredients,
+}
+
+/// Bakes with the pantry from the `pantry` cookie when there is one, and sends the leftovers
+/// back in it. Without one the pantry in the `recipe` cookie is used, as in the original
+/// challenge. Unless `COOKIES` makes the pantry plain, whichever cookie it comes from has to be
+/// signed or encrypted, or the client could bake with any pantry it likes.
+async fn task_3(
+  State(keys): State<Arc<CookieKeys>>,
+  jar: CookieJar,
+) -> Result<impl IntoResponse, AppError> {
+  let invalid = |e: serde_json::Error| BadRequest(format!("Invalid cookie: {e}"));
+
+  let forged =
+    |protection: Protection| protection == Protection::Plain && keys.pantry != Protection::Plain;
+
+  let (recipe, recipe_protection) = read_cookie(&keys, &jar, "recipe")?;
+  let parsed = serde_json::from_slice::<CookieData>(&recipe).map_err(invalid)?;
+
+  let mut pantry = if jar.get("pantry").is_some() {
+    let (saved, protection) = read_cookie(&keys, &jar, "pantry")?;
+    if forged(protection) {
+      return Err(BadRequest("The pantry cookie has to be signed or encrypted".to_string()).into());
+    }
+
+    serde_json::from_slice(&saved).map_err(invalid)?
+  } else {
+    if forged(recipe_protection) {
+      return Err(
+        BadRequest(
+          "Missing pantry cookie, a plain recipe cookie can't bring its own pantry".to_string(),
+        )
+        .into(),
+      );
+    }
+
+    parsed.pantry
+  };
+
+  let recipe = parsed.recipe;
+
+  // Ingredients the recipe needs none of don't limit anything
+  let cookies = recipe
+    .iter()
+    .filter(|(_, needed)| **needed > 0)
+    .map(|(ingredient, needed)| pantry.get(ingredient).copied().unwrap_or_default() / needed)
+    .min()
+    .unwrap_or(u64::MAX);
+
+  for (key, pantry_value) in &mut pantry {
+    let used = cookies.saturating_mul(recipe.get(key).copied().unwrap_or_default());
+    *pantry_value = pantry_value.saturating_sub(used);
+  }
+
+  let saved = keys.seal("pantry", &serde_json::to_vec(&pantry)?, keys.pantry);
+  let set_cookie = format!("pantry={saved}; Path=/7; HttpOnly; SameSite=Strict");
+
+  let res = json!({
+    "cookies": cookies,
+    "pantry": pantry
+  });
+
+  Ok(([(header::SET_COOKIE, set_cookie)], Json::<Value>(res)))
+}
@@ -0,0 +1,730 @@
+//! Plans what to bake from a pantry across several recipes.
+//!
+//! Every ingredient is measured in one dimension, mass, volume or count, taken from the first
+//! place it shows up: the pantry, then the recipes, then the substitutions. Quantities in other
+//! units of that dimension are converted, and mass and volume convert into each other when the
+//! ingredient has a density.
+//!
+//! Ingredients shared between recipes, and substitutes shared between ingredients, are split with
+//! a linear program. Its cookie counts are rounded down and then topped up one recipe at a time,
+//! which lands within about a cookie per recipe of the best possible without the search an exact
+//! integer solution can take.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::Json;
+use microlp::{ComparisonOp, LinearExpr, OptimizationDirection, Problem, Variable};
+use serde::{Deserialize, Serialize};
+
+use crate::days::{AppError, BadRequest};
+
+/// Anything smaller is rounding noise from the solver.
+const EPSILON: f64 = 1e-9;
+
+/// What using up one unit of a substitute costs when planning, against one for buying a unit.
+const SUBSTITUTE_COST: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+  Mass,
+  Volume,
+  Count,
+}
+
+impl Dimension {
+  const fn name(self) -> &'static str {
+    match self {
+      Self::Mass => "mass",
+      Self::Volume => "volume",
+      Self::Count => "pieces",
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Unit {
+  name: &'static str,
+  dimension: Dimension,
+  /// Grams, millilitres or pieces in one of this unit.
+  factor: f64,
+}
+
+impl Unit {
+  const PIECE: Self = Self::new("piece", Dimension::Count, 1.0);
+
+  const fn new(name: &'static str, dimension: Dimension, factor: f64) -> Self {
+    Self {
+      name,
+      dimension,
+      factor,
+    }
+  }
+
+  fn parse(name: &str) -> Option<Self> {
+    let unit = match name.trim().to_lowercase().as_str() {
+      "mg" => Self::new("mg", Dimension::Mass, 0.001),
+      "g" | "gram" | "grams" => Self::new("g", Dimension::Mass, 1.0),
+      "kg" => Self::new("kg", Dimension::Mass, 1000.0),
+      "oz" => Self::new("oz", Dimension::Mass, 28.349_523_125),
+      "lb" | "lbs" => Self::new("lb", Dimension::Mass, 453.592_37),
+      "ml" => Self::new("ml", Dimension::Volume, 1.0),
+      "l" => Self::new("l", Dimension::Volume, 1000.0),
+      "tsp" => Self::new("tsp", Dimension::Volume, 4.928_921_593_75),
+      "tbsp" => Self::new("tbsp", Dimension::Volume, 14.786_764_781_25),
+      "cup" | "cups" => Self::new("cup", Dimension::Volume, 236.588_236_5),
+      "" | "piece" | "pieces" | "pc" | "pcs" => Self::PIECE,
+      _ => return None,
+    };
+
+    Some(unit)
+  }
+}
+
+/// Either a plain number of pieces, a string like `"250 g"` or `{"amount": 250, "unit": "g"}`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "RawQuantity")]
+pub struct Quantity {
+  amount: f64,
+  unit: Unit,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawQuantity {
+  Pieces(f64),
+  Text(String),
+  Object { amount: f64, unit: String },
+}
+
+impl TryFrom<RawQuantity> for Quantity {
+  type Error = String;
+
+  fn try_from(raw: RawQuantity) -> Result<Self, Self::Error> {
+    let (amount, unit) = match raw {
+      RawQuantity::Pieces(amount) => (amount, Unit::PIECE),
+      RawQuantity::Text(text) => {
+        let text = text.trim();
+        // Either "250 g", which allows exponents, or "250g"
+        let (amount, unit) = text.split_once(char::is_whitespace).unwrap_or_else(|| {
+          let split = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len());
+
+          text.split_at(split)
+        });
+
+        let amount = amount
+          .parse()
+          .map_err(|_| format!("Invalid quantity {text:?}"))?;
+        let unit = Unit::parse(unit).ok_or_else(|| format!("Unknown unit in {text:?}"))?;
+
+        (amount, unit)
+      }
+      RawQuantity::Object { amount, unit } => (
+        amount,
+        Unit::parse(&unit).ok_or_else(|| format!("Unknown unit {unit:?}"))?,
+      ),
+    };
+
+    if !amount.is_finite() || amount < 0.0 {
+      return Err(format!("Invalid amount {amount}"));
+    }
+
+    Ok(Self { amount, unit })
+  }
+}
+
+impl Serialize for Quantity {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Out {
+      amount: f64,
+      unit: &'static str,
+    }
+
+    Out {
+      amount: (self.amount * 1e6).round() / 1e6,
+      unit: self.unit.name,
+    }
+    .serialize(serializer)
+  }
+}
+
+impl Quantity {
+  fn base(self) -> f64 {
+    self.amount * self.unit.factor
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Substitution {
+  with: String,
+  /// How much of the substitute replaces `replaces` of the ingredient. Leave both out to swap
+  /// them one for one, converting with the substitute's density if needed.
+  amount: Option<Quantity>,
+  replaces: Option<Quantity>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlanRequest {
+  recipes: BTreeMap<String, BTreeMap<String, Quantity>>,
+  pantry: BTreeMap<String, Quantity>,
+  /// Substitutes for an ingredient, used when there isn't enough of it.
+  #[serde(default)]
+  substitutions: BTreeMap<String, Vec<Substitution>>,
+  /// Grams per millilitre.
+  #[serde(default)]
+  densities: HashMap<String, f64>,
+  /// How many cookies of each recipe to bake. Without one as many cookies as possible are baked
+  /// in total.
+  order: Option<BTreeMap<String, u32>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubstitutionUsed {
+  recipe: String,
+  ingredient: String,
+  with: String,
+  amount: Quantity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlanResponse {
+  cookies: BTreeMap<String, u64>,
+  total: u64,
+  /// What is left, in the units the pantry uses.
+  pantry: BTreeMap<String, Quantity>,
+  substitutions: Vec<SubstitutionUsed>,
+  /// What to buy to bake the whole order from the pantry as it was.
+  shopping_list: BTreeMap<String, Quantity>,
+}
+
+/// Every ingredient by index, with the dimension it is measured in.
+#[derive(Debug, Default)]
+struct Ingredients<'a> {
+  index: HashMap<&'a str, usize>,
+  names: Vec<&'a str>,
+  /// The unit quantities of the ingredient are shown in.
+  units: Vec<Unit>,
+  densities: HashMap<String, f64>,
+}
+
+impl<'a> Ingredients<'a> {
+  fn get(&self, name: &str) -> Option<usize> {
+    self.index.get(name).copied()
+  }
+
+  /// Returns the ingredient's index along with the quantity in its base unit, registering it if
+  /// it is new.
+  fn resolve(&mut self, name: &'a str, quantity: Quantity) -> Result<(usize, f64), BadRequest> {
+    if let Some(id) = self.get(name) {
+      let amount = self.convert(id, quantity.base(), quantity.unit.dimension)?;
+      return Ok((id, amount));
+    }
+
+    let id = self.names.len();
+    let _ = self.index.insert(name, id);
+    self.names.push(name);
+    self.units.push(quantity.unit);
+
+    Ok((id, quantity.base()))
+  }
+
+  /// Converts an amount in the base unit of `from` to the base unit of the ingredient.
+  fn convert(&self, id: usize, amount: f64, from: Dimension) -> Result<f64, BadRequest> {
+    let to = self.units[id].dimension;
+    let name = self.names[id];
+    let density = || {
+      self
+        .densities
+        .get(name)
+        .copied()
+        .filter(|el| el.is_finite() && *el > 0.0)
+        .ok_or_else(|| {
+          BadRequest(format!(
+            "{name} needs a density to convert between mass and volume"
+          ))
+        })
+    };
+
+    match (from, to) {
+      (from, to) if from == to => Ok(amount),
+      (Dimension::Mass, Dimension::Volume) => Ok(amount / density()?),
+      (Dimension::Volume, Dimension::Mass) => Ok(amount * density()?),
+      _ => Err(BadRequest(format!(
+        "{name} is measured in {}, it can't be converted from {}",
+        to.name(),
+        from.name()
+      ))),
+    }
+  }
+
+  fn quantity(&self, id: usize, amount: f64) -> Quantity {
+    let unit = self.units[id];
+
+    Quantity {
+      amount: amount / unit.factor,
+      unit,
+    }
+  }
+}
+
+/// A substitute, with how many base units of it replace one base unit of the ingredient.
+#[derive(Debug, Clone, Copy)]
+struct Substitute {
+  id: usize,
+  factor: f64,
+}
+
+#[derive(Debug)]
+struct Recipe<'a> {
+  name: &'a str,
+  /// Base units of every ingredient needed for one cookie.
+  needs: Vec<(usize, f64)>,
+}
+
+#[derive(Debug)]
+struct Kitchen<'a> {
+  ingredients: Ingredients<'a>,
+  recipes: Vec<Recipe<'a>>,
+  pantry: Vec<f64>,
+  /// Substitutes of every ingredient.
+  substitutes: Vec<Vec<Substitute>>,
+}
+
+impl<'a> Kitchen<'a> {
+  fn new(req: &'a PlanRequest) -> Result<Self, BadRequest> {
+    let mut ingredients = Ingredients {
+      densities: req.densities.clone(),
+      ..Ingredients::default()
+    };
+
+    let mut pantry = Vec::new();
+    for (name, quantity) in &req.pantry {
+      let (_, amount) = ingredients.resolve(name, *quantity)?;
+      pantry.push(amount);
+    }
+
+    let mut recipes = Vec::new();
+    for (name, needs) in &req.recipes {
+      // Nothing is needed of ingredients listed with none, whatever unit they are in
+      let needs = needs
+        .iter()
+        .filter(|(_, quantity)| quantity.amount > 0.0)
+        .map(|(ingredient, quantity)| ingredients.resolve(ingredient, *quantity))
+        .collect::<Result<Vec<_>, _>>()?;
+
+      if needs.is_empty() {
+        return Err(BadRequest(format!("Recipe {name} needs no ingredients")));
+      }
+
+      recipes.push(Recipe { name, needs });
+    }
+    if recipes.is_empty() {
+      return Err(BadRequest("No recipes".to_string()));
+    }
+
+    let mut substitutes = Vec::new();
+    for (name, subs) in &req.substitutions {
+      let Some(id) = ingredients.get(name) else {
+        // Nothing needs it
+        continue;
+      };
+
+      for sub in subs {
+        if sub.with == *name {
+          return Err(BadRequest(format!("{name} can't substitute itself")));
+        }
+
+        let factor = match (sub.amount, sub.replaces) {
+          (Some(amount), Some(replaces)) => {
+            let replaces = ingredients.convert(id, replaces.base(), replaces.unit.dimension)?;
+            let (_, amount) = ingredients.resolve(&sub.with, amount)?;
+            if replaces <= 0.0 || amount <= 0.0 {
+              return Err(BadRequest(format!(
+                "Substituting {} for {name} needs amounts above zero",
+                sub.with
+              )));
+            }
+
+            amount / replaces
+          }
+          (None, None) => {
+            let unit = ingredients.units[id];
+            let one = Quantity { amount: 1.0, unit }.base();
+            let (_, amount) = ingredients.resolve(&sub.with, Quantity { amount: 1.0, unit })?;
+
+            amount / one
+          }
+          _ => {
+            return Err(BadRequest(format!(
+              "Substituting {} for {name} needs both amount and replaces",
+              sub.with
+            )))
+          }
+        };
+
+        let with = ingredients.get(&sub.with).unwrap_or_default();
+        substitutes.resize_with(ingredients.names.len(), Vec::new);
+        substitutes[id].push(Substitute { id: with, factor });
+      }
+    }
+
+    // Ingredients only in recipes or substitutions have none
+    pantry.resize(ingredients.names.len(), 0.0);
+    substitutes.resize_with(ingredients.names.len(), Vec::new);
+
+    Ok(Self {
+      ingredients,
+      recipes,
+      pantry,
+      substitutes,
+    })
+  }
+
+  /// The most cookies of the recipe the pantry could make on its own.
+  fn upper_bound(&self, recipe: &Recipe) -> f64 {
+    recipe
+      .needs
+      .iter()
+      .map(|(id, need)| {
+        let available = self.pantry[*id]
+          + self.substitutes[*id]
+            .iter()
+            .map(|el| self.pantry[el.id] / el.factor)
+            .sum::<f64>();
+
+        (available / need + EPSILON).floor()
+      })
+      .fold(f64::INFINITY, f64::min)
+  }
+}
+
+/// How one recipe's need of an ingredient is covered, by the ingredient itself first and then
+/// by each substitute.
+#[derive(Debug)]
+struct Cover {
+  recipe: usize,
+  ingredient: usize,
+  /// Along with the substitute they stand for, `None` for the ingredient itself.
+  vars: Vec<(Option<Substitute>, Variable)>,
+}
+
+/// The linear program every plan is built on. Cookie counts are either known or variables.
+struct Model {
+  problem: Problem,
+  cookies: Vec<Option<Variable>>,
+  covers: Vec<Cover>,
+  /// Usage of each ingredient, less what is bought.
+  usage: Vec<LinearExpr>,
+}
+
+impl Model {
+  fn new(kitchen: &Kitchen, direction: OptimizationDirection, counts: &[Count]) -> Self {
+    let mut problem = Problem::new(direction);
+    let mut usage = vec![Vec::new(); kitchen.ingredients.names.len()];
+    let mut covers = Vec::new();
+
+    let cookies = counts
+      .iter()
+      .map(|el| match el {
+        Count::Fixed(_) => None,
+        Count::Bounded(max) => Some(problem.add_var(1.0, (0.0, f64::from(*max)))),
+      })
+      .collect::<Vec<_>>();
+
+    for (r, recipe) in kitchen.recipes.iter().enumerate() {
+      for (id, need) in &recipe.needs {
+        let subs = &kitchen.substitutes[*id];
+
+        // When minimizing, substitutes cost something so they're only used when needed
+        let cost = if direction == OptimizationDirection::Minimize {
+          SUBSTITUTE_COST
+        } else {
+          0.0
+        };
+        let mut vars = vec![(None, problem.add_var(0.0, (0.0, f64::INFINITY)))];
+        vars.extend(
+          subs
+            .iter()
+            .map(|el| (Some(*el), problem.add_var(cost, (0.0, f64::INFINITY)))),
+        );
+
+        let mut covered = vars.iter().map(|(_, var)| (*var, 1.0)).collect::<Vec<_>>();
+        let rhs = match (cookies[r], &counts[r]) {
+          (Some(var), _) => {
+            covered.push((var, -need));
+            0.0
+          }
+          (None, Count::Fixed(count) | Count::Bounded(count)) => need * f64::from(*count),
+        };
+        problem.add_constraint(covered, ComparisonOp::Eq, rhs);
+
+        for (sub, var) in &vars {
+          match sub {
+            None => usage[*id].push((*var, 1.0)),
+            Some(sub) => usage[sub.id].push((*var, sub.factor)),
+          }
+        }
+
+        covers.push(Cover {
+          recipe: r,
+          ingredient: *id,
+          vars,
+        });
+      }
+    }
+
+    Self {
+      problem,
+      cookies,
+      covers,
+      usage: usage.into_iter().map(LinearExpr::from).collect(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Count {
+  Fixed(u32),
+  /// Up to this many, the solver picks.
+  Bounded(u32),
+}
+
+/// Bakes as many cookies as possible, up to the order when there is one.
+fn bake(kitchen: &Kitchen, order: Option<&[u32]>) -> Result<Vec<u32>, anyhow::Error> {
+  let bounds = kitchen
+    .recipes
+    .iter()
+    .enumerate()
+    .map(|(r, recipe)| {
+      #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+      let max = kitchen.upper_bound(recipe).min(f64::from(u32::MAX)) as u32;
+
+      order.map_or(max, |el| el[r].min(max))
+    })
+    .collect::<Vec<_>>();
+
+  let counts = bounds
+    .iter()
+    .map(|el| Count::Bounded(*el))
+    .collect::<Vec<_>>();
+  let mut model = Model::new(kitchen, OptimizationDirection::Maximize, &counts);
+  for (usage, available) in model.usage.into_iter().zip(&kitchen.pantry) {
+    model
+      .problem
+      .add_constraint(usage, ComparisonOp::Le, available * (1.0 + EPSILON));
+  }
+
+  let solution = model.problem.solve()?;
+  let relaxed = model
+    .cookies
+    .iter()
+    .map(|el| el.map_or(0.0, |var| *solution.var_value(var)))
+    .collect::<Vec<_>>();
+
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  let mut counts = relaxed
+    .iter()
+    .map(|el| (el + EPSILON).floor().max(0.0) as u32)
+    .collect::<Vec<_>>();
+
+  // Rounding down leaves less than a cookie of each recipe behind, top up whatever still fits,
+  // those that were cut the most first. Adding cookies only ever takes from the pantry so a
+  // recipe that doesn't fit once never will.
+  let mut by_remainder = (0..counts.len()).collect::<Vec<_>>();
+  by_remainder.sort_by(|a, b| {
+    let remainder = |r: usize| relaxed[r] - f64::from(counts[r]);
+    remainder(*b).total_cmp(&remainder(*a))
+  });
+
+  for r in by_remainder {
+    let mut step = 1;
+    while counts[r] < bounds[r] {
+      let mut next = counts.clone();
+      next[r] = counts[r].saturating_add(step).min(bounds[r]);
+
+      if fits(kitchen, &next) {
+        counts = next;
+        step = step.saturating_mul(2);
+      } else if step == 1 {
+        break;
+      } else {
+        step = 1;
+      }
+    }
+  }
+
+  Ok(counts)
+}
+
+/// Whether the pantry has enough for exactly `counts`.
+fn fits(kitchen: &Kitchen, counts: &[u32]) -> bool {
+  let counts = counts
+    .iter()
+    .map(|el| Count::Fixed(*el))
+    .collect::<Vec<_>>();
+  let mut model = Model::new(kitchen, OptimizationDirection::Minimize, &counts);
+  for (usage, available) in model.usage.into_iter().zip(&kitchen.pantry) {
+    model
+      .problem
+      .add_constraint(usage, ComparisonOp::Le, available * (1.0 + EPSILON));
+  }
+
+  model.problem.solve().is_ok()
+}
+
+/// What baking exactly `counts` takes out of the pantry, and what has to be bought first when the
+/// pantry isn't enough.
+fn consume(kitchen: &Kitchen, counts: &[u32]) -> Result<Consumption, anyhow::Error> {
+  let counts = counts
+    .iter()
+    .map(|el| Count::Fixed(*el))
+    .collect::<Vec<_>>();
+  let mut model = Model::new(kitchen, OptimizationDirection::Minimize, &counts);
+
+  // Only what recipes call for is bought, substitutes are for using up the pantry
+  let needed = kitchen
+    .recipes
+    .iter()
+    .flat_map(|el| el.needs.iter().map(|(id, _)| *id))
+    .collect::<std::collections::HashSet<_>>();
+
+  let mut bought = Vec::new();
+  for (id, (mut usage, available)) in model.usage.into_iter().zip(&kitchen.pantry).enumerate() {
+    if needed.contains(&id) {
+      let var = model.problem.add_var(1.0, (0.0, f64::INFINITY));
+      usage.add(var, -1.0);
+      bought.push((id, var));
+    }
+
+    model
+      .problem
+      .add_constraint(usage, ComparisonOp::Le, available * (1.0 + EPSILON));
+  }
+
+  let solution = model.problem.solve()?;
+  let value = |var| solution.var_value(var).max(0.0);
+
+  let mut used = vec![0.0; kitchen.pantry.len()];
+  let mut substitutions = Vec::new();
+  for cover in &model.covers {
+    for (sub, var) in &cover.vars {
+      let amount = value(*var);
+      match sub {
+        None => used[cover.ingredient] += amount,
+        Some(sub) => {
+          used[sub.id] += amount * sub.factor;
+          if amount > EPSILON {
+            substitutions.push((cover.recipe, cover.ingredient, sub.id, amount * sub.factor));
+          }
+        }
+      }
+    }
+  }
+
+  let bought = bought
+    .into_iter()
+    .map(|(id, var)| (id, value(var)))
+    .filter(|(id, amount)| *amount > EPSILON * kitchen.pantry[*id].max(1.0))
+    .collect();
+
+  Ok(Consumption {
+    used,
+    substitutions,
+    bought,
+  })
+}
+
+#[derive(Debug)]
+struct Consumption {
+  used: Vec<f64>,
+  /// Recipe, ingredient, substitute and how much of it.
+  substitutions: Vec<(usize, usize, usize, f64)>,
+  bought: Vec<(usize, f64)>,
+}
+
+pub async fn plan(Json(req): Json<PlanRequest>) -> Result<Json<PlanResponse>, AppError> {
+  let kitchen = Kitchen::new(&req)?;
+
+  if let Some(name) = req
+    .order
+    .iter()
+    .flat_map(BTreeMap::keys)
+    .find(|el| !req.recipes.contains_key(*el))
+  {
+    return Err(BadRequest(format!("Unknown recipe {name} in the order")).into());
+  }
+
+  let order = req.order.as_ref().map(|order| {
+    kitchen
+      .recipes
+      .iter()
+      .map(|el| order.get(el.name).copied().unwrap_or_default())
+      .collect::<Vec<_>>()
+  });
+
+  let baked = bake(&kitchen, order.as_deref())?;
+  let consumption = consume(&kitchen, &baked)?;
+
+  let shopping_list = match &order {
+    Some(order) if *order != baked => consume(&kitchen, order)?
+      .bought
+      .into_iter()
+      .map(|(id, amount)| {
+        let name = kitchen.ingredients.names[id].to_string();
+        let mut quantity = kitchen.ingredients.quantity(id, amount);
+        // Nobody sells half an egg
+        if quantity.unit.dimension == Dimension::Count {
+          quantity.amount = (quantity.amount - EPSILON).ceil();
+        }
+
+        (name, quantity)
+      })
+      .collect(),
+    _ => BTreeMap::new(),
+  };
+
+  let ingredients = &kitchen.ingredients;
+  let pantry = req
+    .pantry
+    .keys()
+    .filter_map(|name| {
+      let id = ingredients.get(name)?;
+      let left = kitchen.pantry[id] - consumption.used[id];
+      let left = if left < EPSILON * kitchen.pantry[id].max(1.0) {
+        0.0
+      } else {
+        left
+      };
+
+      Some((name.clone(), ingredients.quantity(id, left)))
+    })
+    .collect();
+
+  let substitutions = consumption
+    .substitutions
+    .into_iter()
+    .map(|(recipe, ingredient, with, amount)| SubstitutionUsed {
+      recipe: kitchen.recipes[recipe].name.to_string(),
+      ingredient: ingredients.names[ingredient].to_string(),
+      with: ingredients.names[with].to_string(),
+      amount: ingredients.quantity(with, amount),
+    })
+    .collect();
+
+  let cookies = kitchen
+    .recipes
+    .iter()
+    .zip(&baked)
+    .map(|(recipe, count)| (recipe.name.to_string(), u64::from(*count)))
+    .collect::<BTreeMap<_, _>>();
+
+  Ok(Json(PlanResponse {
+    total: cookies.values().sum(),
+    cookies,
+    pantry,
+    substitutions,
+    shopping_list,
+  }))
+}
@@ -5,11 +5,12 @@ use axum::{
   routing::{get, post},
   Json, Router,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sqlx::PgPool;
 
 use super::AppError;
+use crate::feed::{self, Change};
 
 // use super::AppError;
 
@@ -60,12 +61,12 @@ async fn reset(State(state): State<MyState>) -> Result<(), AppError> {
   Ok(())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub(crate) struct Order {
-  id: i32,
-  region_id: i32,
-  gift_name: String,
-  quantity: i32,
+  pub(crate) id: i32,
+  pub(crate) region_id: i32,
+  pub(crate) gift_name: String,
+  pub(crate) quantity: i32,
 }
 
 pub(crate) async fn insert(
@@ -73,6 +74,8 @@ pub(crate) async fn insert(
   Json(payload): Json<Vec<Order>>,
 ) -> Result<impl IntoResponse, AppError> {
   // dbg!(&payload);
+  let mut tx = state.pool.begin().await?;
+
   for el in payload {
     let _ = sqlx::query!(
       "INSERT INTO orders (id, region_id, gift_name, quantity) VALUES ($1, $2, $3, $4)",
@@ -81,10 +84,14 @@ pub(crate) async fn insert(
       el.gift_name,
       el.quantity
     )
-    .execute(&state.pool)
+    .execute(&mut *tx)
     .await?;
+
+    feed::publish(&mut *tx, &Change::OrderInserted(el)).await?;
   }
 
+  tx.commit().await?;
+
   Ok(())
 }
 
@@ -0,0 +1,225 @@
+//! CommonMark with the GitHub extensions the elves use in their notes: tables, task lists and
+//! strikethrough. Parsing is left to pulldown-cmark, this only adds the heading ids, anchors and
+//! table of contents.
+//!
+//! Raw HTML in the notes is written out as is, so what this returns still has to go through the
+//! sanitizer.
+
+use std::collections::BTreeSet;
+
+use pulldown_cmark::{html, Alignment, Event, Parser, Tag, TagEnd};
+use serde::Deserialize;
+
+use super::{escape_html, sanitize::decode_entities};
+
+const EXTENSIONS: pulldown_cmark::Options = pulldown_cmark::Options::ENABLE_TABLES
+  .union(pulldown_cmark::Options::ENABLE_TASKLISTS)
+  .union(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Options {
+  /// Adds a nested list linking to every heading before the document.
+  pub toc: bool,
+  /// Gives headings an id and a link to themselves.
+  pub anchors: bool,
+}
+
+#[derive(Debug)]
+pub struct Rendered {
+  pub html: String,
+  /// The text of the first top level heading, if there is one.
+  pub title: Option<String>,
+}
+
+pub fn render(markdown: &str, options: &Options) -> Rendered {
+  let mut renderer = Renderer {
+    options,
+    headings: Vec::new(),
+    ids: BTreeSet::new(),
+  };
+
+  let mut events = Vec::new();
+  let mut parser = Parser::new_ext(markdown, EXTENSIONS);
+  // The alignment of every column of the table being written, and whether it is in the head
+  let mut table = (Vec::new(), false);
+  let mut column = 0;
+
+  while let Some(event) = parser.next() {
+    let event = match event {
+      Event::Start(Tag::Heading { level, .. }) => {
+        let content = parser
+          .by_ref()
+          .take_while(|el| !matches!(el, Event::End(TagEnd::Heading(_))))
+          .collect::<Vec<_>>();
+        renderer.heading(level as usize, content)
+      }
+      // pulldown-cmark aligns cells with a style, which the sanitizer drops
+      Event::Start(Tag::Table(align)) => {
+        table = (align, false);
+        Event::Start(Tag::Table(Vec::new()))
+      }
+      Event::Start(Tag::TableHead) => {
+        table.1 = true;
+        column = 0;
+        Event::Start(Tag::TableHead)
+      }
+      Event::End(TagEnd::TableHead) => {
+        table.1 = false;
+        Event::End(TagEnd::TableHead)
+      }
+      Event::Start(Tag::TableRow) => {
+        column = 0;
+        Event::Start(Tag::TableRow)
+      }
+      Event::Start(Tag::TableCell) => {
+        let cell = if table.1 { "th" } else { "td" };
+        let html = match table.0.get(column) {
+          Some(Alignment::Left) => format!("<{cell} align=\"left\">"),
+          Some(Alignment::Center) => format!("<{cell} align=\"center\">"),
+          Some(Alignment::Right) => format!("<{cell} align=\"right\">"),
+          Some(Alignment::None) | None => format!("<{cell}>"),
+        };
+        column += 1;
+        Event::InlineHtml(html.into())
+      }
+      Event::End(TagEnd::TableCell) => {
+        Event::InlineHtml(if table.1 { "</th>\n" } else { "</td>\n" }.into())
+      }
+      event => event,
+    };
+    events.push(event);
+  }
+
+  let mut html = String::with_capacity(markdown.len() * 3 / 2);
+  html::push_html(&mut html, events.into_iter());
+
+  let title = renderer
+    .headings
+    .iter()
+    .find(|el| el.level == 1)
+    .map(|el| el.text.clone());
+  if options.toc && !renderer.headings.is_empty() {
+    html.insert_str(0, &renderer.toc());
+  }
+
+  Rendered { html, title }
+}
+
+/// Just the text of some HTML, entities decoded.
+fn plain_text(html: &str) -> String {
+  let mut res = String::with_capacity(html.len());
+  let mut rest = html;
+  while let Some(start) = rest.find('<') {
+    res.push_str(&rest[..start]);
+    rest = rest[start..]
+      .find('>')
+      .map_or("", |el| &rest[start + el + 1..]);
+  }
+  res.push_str(rest);
+
+  decode_entities(&res)
+}
+
+/// Lowercase words joined with dashes, like GitHub makes them.
+fn slug(text: &str) -> String {
+  text
+    .trim()
+    .to_lowercase()
+    .chars()
+    .filter_map(|el| match el {
+      el if el.is_alphanumeric() || el == '-' || el == '_' => Some(el),
+      el if el.is_whitespace() => Some('-'),
+      _ => None,
+    })
+    .collect()
+}
+
+struct Heading {
+  level: usize,
+  id: String,
+  text: String,
+}
+
+struct Renderer<'a> {
+  options: &'a Options,
+  headings: Vec<Heading>,
+  ids: BTreeSet<String>,
+}
+
+impl Renderer<'_> {
+  /// The whole heading as HTML, with its id and anchor when asked for.
+  fn heading(&mut self, level: usize, content: Vec<Event<'_>>) -> Event<'static> {
+    let mut inner = String::new();
+    html::push_html(&mut inner, content.into_iter());
+    let text = plain_text(&inner);
+
+    let mut out = String::new();
+    if self.options.anchors || self.options.toc {
+      let id = self.unique_id(&text);
+      out.push_str(&format!("<h{level} id=\"{id}\">"));
+      if self.options.anchors {
+        out.push_str(&format!("<a class=\"anchor\" href=\"#{id}\">#</a> "));
+      }
+      self.headings.push(Heading { level, id, text });
+    } else {
+      out.push_str(&format!("<h{level}>"));
+      self.headings.push(Heading {
+        level,
+        id: String::new(),
+        text,
+      });
+    }
+    out.push_str(&format!("{inner}</h{level}>\n"));
+
+    Event::Html(out.into())
+  }
+
+  /// A heading id that isn't taken yet, numbered like `intro-1` when it is.
+  fn unique_id(&mut self, text: &str) -> String {
+    let base = match slug(text) {
+      el if el.is_empty() => "section".to_string(),
+      el => el,
+    };
+
+    let mut id = base.clone();
+    let mut n = 0;
+    while self.ids.contains(&id) {
+      n += 1;
+      id = format!("{base}-{n}");
+    }
+    let _ = self.ids.insert(id.clone());
+
+    id
+  }
+
+  fn toc(&self) -> String {
+    let mut res = String::from("<div class=\"toc\">\n");
+    let mut levels = Vec::<usize>::new();
+
+    for heading in &self.headings {
+      while levels.last().is_some_and(|el| *el > heading.level) {
+        let _ = levels.pop();
+        res.push_str("</li>\n</ul>\n");
+      }
+      if levels.last() == Some(&heading.level) {
+        res.push_str("</li>\n");
+      } else {
+        levels.push(heading.level);
+        res.push_str("<ul>\n");
+      }
+
+      res.push_str(&format!(
+        "<li><a href=\"#{}\">{}</a>",
+        heading.id,
+        escape_html(&heading.text)
+      ));
+    }
+    for _ in levels {
+      res.push_str("</li>\n</ul>\n");
+    }
+    res.push_str("</div>\n");
+
+    res
+  }
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use axum::{
+  extract::State,
+  response::{IntoResponse, Response},
+  routing::post,
+  Json, Router,
+};
+use serde::Deserialize;
+
+use super::{AppError, BadRequest};
+use markdown::Options;
+use sanitize::Policy;
+use template::{HtmlFragment, HtmlPage, DEFAULT_TITLE};
+
+mod markdown;
+mod sanitize;
+mod template;
+
+pub fn get_routes() -> Router {
+  Router::new()
+    .route("/14/unsafe", post(unsafe_render))
+    .route("/14/safe", post(safe_render))
+    .route("/14/rich", post(rich_render))
+    .route("/14/markdown", post(markdown_render))
+    .with_state(Arc::new(Policy::from_env()))
+}
+
+#[derive(Deserialize, Debug)]
+struct SimpleBody {
+  content: String,
+  title: Option<String>,
+  /// Only on the routes that don't sanitize, the others take it from the policy.
+  css: Option<String>,
+  /// More content, each shown after `content` in the same way.
+  #[serde(default)]
+  blocks: Vec<String>,
+}
+
+impl SimpleBody {
+  fn page(self, css: Option<String>, render: impl Fn(&str) -> String) -> HtmlPage {
+    let blocks = std::iter::once(&self.content)
+      .chain(&self.blocks)
+      .map(|el| render(el))
+      .collect();
+
+    HtmlPage {
+      title: self.title.unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+      css,
+      blocks,
+    }
+  }
+}
+
+async fn unsafe_render(Json(mut payload): Json<SimpleBody>) -> HtmlPage {
+  let css = payload.css.take();
+  payload.page(css, str::to_string)
+}
+
+pub(crate) fn escape_html(unsanitized: &str) -> String {
+  let mut res = String::with_capacity(unsanitized.len());
+  for c in unsanitized.chars() {
+    match c {
+      '&' => res.push_str("&amp;"),
+      '<' => res.push_str("&lt;"),
+      '>' => res.push_str("&gt;"),
+      '"' => res.push_str("&quot;"),
+      '\'' => res.push_str("&#39;"),
+      c => res.push(c),
+    }
+  }
+
+  res
+}
+
+async fn safe_render(Json(mut payload): Json<SimpleBody>) -> HtmlPage {
+  let css = payload.css.take();
+  payload.page(css, escape_html)
+}
+
+/// Sanitized HTML is styled by the policy only.
+fn no_client_css(css: Option<&str>) -> Result<(), BadRequest> {
+  match css {
+    Some(_) => Err(BadRequest(
+      "css can't be sent along with HTML that is sanitized".to_string(),
+    )),
+    None => Ok(()),
+  }
+}
+
+/// Keeps the HTML the policy allows.
+async fn rich_render(
+  State(policy): State<Arc<Policy>>,
+  Json(payload): Json<SimpleBody>,
+) -> Result<HtmlPage, AppError> {
+  no_client_css(payload.css.as_deref())?;
+
+  Ok(payload.page(policy.css.clone(), |el| policy.sanitize(el)))
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum Output {
+  /// Just the rendered HTML.
+  Fragment,
+  #[default]
+  Page,
+}
+
+#[derive(Deserialize, Debug)]
+struct MarkdownBody {
+  markdown: String,
+  #[serde(flatten)]
+  options: Options,
+  #[serde(default)]
+  output: Output,
+  /// The first top level heading when left out.
+  title: Option<String>,
+  /// Refused, the policy has the stylesheet.
+  css: Option<String>,
+}
+
+async fn markdown_render(
+  State(policy): State<Arc<Policy>>,
+  Json(payload): Json<MarkdownBody>,
+) -> Result<Response, AppError> {
+  no_client_css(payload.css.as_deref())?;
+
+  let rendered = markdown::render(&payload.markdown, &payload.options);
+  let html = policy.sanitize(rendered.html.trim_end());
+
+  Ok(match payload.output {
+    Output::Fragment => HtmlFragment(html).into_response(),
+    Output::Page => HtmlPage {
+      title: payload
+        .title
+        .or(rendered.title)
+        .unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+      css: policy.css.clone(),
+      blocks: vec![html],
+    }
+    .into_response(),
+  })
+}
@@ -0,0 +1,518 @@
+//! An allowlist HTML sanitizer for the limited rich text users may send.
+//!
+//! Tags outside the policy are dropped but their text is kept, except for the ones in
+//! [`DROPPED_WITH_CONTENT`], which go away entirely whatever the policy says. Everything that is
+//! kept is written back out escaped, so the output is well formed even when the input isn't.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use super::escape_html;
+
+/// Never allowed, and nothing inside them is shown either.
+const DROPPED_WITH_CONTENT: [&str; 19] = [
+  "script",
+  "style",
+  "iframe",
+  "frame",
+  "frameset",
+  "object",
+  "embed",
+  "applet",
+  "template",
+  "textarea",
+  "title",
+  "noscript",
+  "noembed",
+  "noframes",
+  "xmp",
+  "plaintext",
+  "svg",
+  "math",
+  "select",
+];
+
+/// Their content is text up to the closing tag rather than markup.
+const RAW_TEXT: [&str; 10] = [
+  "script",
+  "style",
+  "iframe",
+  "textarea",
+  "title",
+  "noscript",
+  "noembed",
+  "noframes",
+  "xmp",
+  "plaintext",
+];
+
+const VOID: [&str; 6] = ["br", "hr", "img", "input", "wbr", "col"];
+
+/// Attributes holding URLs, which have to use one of the allowed schemes.
+const URL_ATTRIBUTES: [&str; 3] = ["href", "src", "cite"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+  /// The tags kept, with the attributes each of them may have.
+  pub tags: BTreeMap<String, BTreeSet<String>>,
+  /// Attributes any kept tag may have.
+  pub global_attributes: BTreeSet<String>,
+  /// Schemes URLs may have. Relative URLs are always fine.
+  pub url_schemes: BTreeSet<String>,
+  /// The only values some attributes may have, keyed like `input.type`. Tags without one of them
+  /// are dropped, a bare `input` would be a text field.
+  pub attribute_values: BTreeMap<String, BTreeSet<String>>,
+  /// Set on every link, replacing what the user sent.
+  pub link_rel: Option<String>,
+  /// The stylesheet of pages with sanitized HTML. Clients can't send their own there, it could
+  /// restyle everything the sanitizer kept.
+  pub css: Option<String>,
+}
+
+fn set<const N: usize>(items: [&str; N]) -> BTreeSet<String> {
+  items.into_iter().map(str::to_string).collect()
+}
+
+impl Default for Policy {
+  fn default() -> Self {
+    let mut tags = [
+      "abbr",
+      "b",
+      "blockquote",
+      "br",
+      "code",
+      "del",
+      "em",
+      "hr",
+      "i",
+      "ins",
+      "kbd",
+      "li",
+      "mark",
+      "p",
+      "pre",
+      "s",
+      "small",
+      "span",
+      "strong",
+      "sub",
+      "sup",
+      "table",
+      "tbody",
+      "thead",
+      "tfoot",
+      "tr",
+      "u",
+      "ul",
+      "div",
+    ]
+    .into_iter()
+    .map(|el| (el.to_string(), BTreeSet::new()))
+    .collect::<BTreeMap<_, _>>();
+
+    for heading in ["h1", "h2", "h3", "h4", "h5", "h6"] {
+      let _ = tags.insert(heading.to_string(), set(["id"]));
+    }
+    let _ = tags.insert("a".to_string(), set(["href", "title"]));
+    let _ = tags.insert(
+      "img".to_string(),
+      set(["src", "alt", "title", "width", "height"]),
+    );
+    let _ = tags.insert("ol".to_string(), set(["start"]));
+    let _ = tags.insert("th".to_string(), set(["align", "colspan", "rowspan"]));
+    let _ = tags.insert("td".to_string(), set(["align", "colspan", "rowspan"]));
+    let _ = tags.insert("input".to_string(), set(["type", "checked", "disabled"]));
+
+    Self {
+      tags,
+      global_attributes: set(["class", "title"]),
+      url_schemes: set(["http", "https", "mailto"]),
+      attribute_values: BTreeMap::from([("input.type".to_string(), set(["checkbox"]))]),
+      link_rel: Some("noopener noreferrer nofollow".to_string()),
+      css: None,
+    }
+  }
+}
+
+impl Policy {
+  /// Reads the policy as JSON from `HTML_POLICY`, like
+  /// `{"tags": {"p": [], "a": ["href"]}, "url_schemes": ["https"]}`.
+  pub fn from_env() -> Self {
+    std::env::var("HTML_POLICY")
+      .ok()
+      .and_then(|el| {
+        serde_json::from_str(&el)
+          .map_err(|e| warn!("Ignoring invalid HTML_POLICY: {:?}", e))
+          .ok()
+      })
+      .unwrap_or_default()
+  }
+
+  fn allows_attribute(&self, tag: &str, name: &str, value: &str) -> bool {
+    // Event handlers and inline styles can run code whatever the policy says
+    if name.starts_with("on") || name == "style" || name == "srcdoc" {
+      return false;
+    }
+    if tag == "a" && name == "rel" && self.link_rel.is_some() {
+      return false;
+    }
+
+    let allowed = self.global_attributes.contains(name)
+      || self.tags.get(tag).is_some_and(|el| el.contains(name));
+    if !allowed {
+      return false;
+    }
+
+    if let Some(values) = self.attribute_values.get(&format!("{tag}.{name}")) {
+      if !values.contains(&value.to_ascii_lowercase()) {
+        return false;
+      }
+    }
+
+    !URL_ATTRIBUTES.contains(&name) || self.allows_url(value)
+  }
+
+  /// Whether the tag has an allowed value for every attribute restricted on it.
+  fn has_restricted_attributes(&self, tag: &Tag) -> bool {
+    self
+      .attribute_values
+      .iter()
+      .filter_map(|(key, values)| {
+        let name = key.strip_prefix(tag.name.as_str())?.strip_prefix('.')?;
+        Some((name, values))
+      })
+      .all(|(name, values)| {
+        tag
+          .attributes
+          .iter()
+          .find(|el| el.0 == name)
+          .is_some_and(|el| values.contains(&el.1.to_ascii_lowercase()))
+      })
+  }
+
+  fn allows_url(&self, url: &str) -> bool {
+    // Browsers ignore these inside a scheme, so `java\tscript:` is still javascript
+    let url = url
+      .chars()
+      .filter(|el| !el.is_ascii_whitespace() && !el.is_control())
+      .collect::<String>();
+
+    let end = url.find([':', '/', '?', '#']).unwrap_or(url.len());
+    if !url[end..].starts_with(':') {
+      return true;
+    }
+
+    self.url_schemes.contains(&url[..end].to_ascii_lowercase())
+  }
+
+  pub fn sanitize(&self, html: &str) -> String {
+    Sanitizer {
+      policy: self,
+      res: String::with_capacity(html.len()),
+      open: Vec::new(),
+      skipping: None,
+    }
+    .run(html)
+  }
+}
+
+struct Tag {
+  name: String,
+  attributes: Vec<(String, String)>,
+  end: bool,
+  self_closing: bool,
+}
+
+struct Sanitizer<'a> {
+  policy: &'a Policy,
+  res: String,
+  /// Kept elements that still need closing.
+  open: Vec<String>,
+  /// Inside a dropped element, its name and how many of them are nested.
+  skipping: Option<(String, usize)>,
+}
+
+impl Sanitizer<'_> {
+  fn run(mut self, html: &str) -> String {
+    let mut rest = html;
+
+    while let Some(idx) = rest.find('<') {
+      self.text(&rest[..idx]);
+      rest = &rest[idx..];
+
+      if let Some(after) = rest.strip_prefix("<!--") {
+        rest = after.find("-->").map_or("", |el| &after[el + 3..]);
+      } else if rest.starts_with("<!") || rest.starts_with("<?") {
+        rest = rest.find('>').map_or("", |el| &rest[el + 1..]);
+      } else if let Some((tag, after)) = parse_tag(rest) {
+        rest = after;
+        if !tag.end && RAW_TEXT.contains(&tag.name.as_str()) {
+          rest = skip_raw_text(rest, &tag.name);
+          continue;
+        }
+        self.tag(tag);
+      } else if rest[1..]
+        .strip_prefix('/')
+        .unwrap_or(&rest[1..])
+        .starts_with(|el: char| el.is_ascii_alphabetic())
+      {
+        // A tag that never ends takes the rest of the input with it, like in a browser
+        rest = "";
+      } else {
+        self.text("<");
+        rest = &rest[1..];
+      }
+    }
+    self.text(rest);
+
+    while let Some(name) = self.open.pop() {
+      self.close(&name);
+    }
+
+    self.res
+  }
+
+  fn text(&mut self, text: &str) {
+    if self.skipping.is_none() && !text.is_empty() {
+      self.res.push_str(&escape_html(&decode_entities(text)));
+    }
+  }
+
+  fn close(&mut self, name: &str) {
+    self.res.push_str("</");
+    self.res.push_str(name);
+    self.res.push('>');
+  }
+
+  fn tag(&mut self, tag: Tag) {
+    if let Some((name, depth)) = &mut self.skipping {
+      if *name == tag.name && !tag.self_closing {
+        if !tag.end {
+          *depth += 1;
+        } else if *depth == 1 {
+          self.skipping = None;
+        } else {
+          *depth -= 1;
+        }
+      }
+      return;
+    }
+
+    if DROPPED_WITH_CONTENT.contains(&tag.name.as_str()) {
+      if !tag.end && !tag.self_closing {
+        self.skipping = Some((tag.name, 1));
+      }
+      return;
+    }
+    if !self.policy.tags.contains_key(&tag.name) {
+      return;
+    }
+
+    if tag.end {
+      // Closing an element closes everything still open inside it, a stray end tag does nothing
+      if let Some(idx) = self.open.iter().rposition(|el| *el == tag.name) {
+        for name in self.open.split_off(idx).into_iter().rev() {
+          self.close(&name);
+        }
+      }
+      return;
+    }
+    if !self.policy.has_restricted_attributes(&tag) {
+      return;
+    }
+
+    self.res.push('<');
+    self.res.push_str(&tag.name);
+
+    let mut seen = BTreeSet::new();
+    for (name, value) in &tag.attributes {
+      // Like browsers, the first of duplicate attributes wins
+      if !seen.insert(name.as_str()) || !self.policy.allows_attribute(&tag.name, name, value) {
+        continue;
+      }
+
+      self.res.push(' ');
+      self.res.push_str(name);
+      self.res.push_str("=\"");
+      self.res.push_str(&escape_html(value));
+      self.res.push('"');
+    }
+
+    if tag.name == "a" {
+      if let Some(rel) = &self.policy.link_rel {
+        self.res.push_str(" rel=\"");
+        self.res.push_str(&escape_html(rel));
+        self.res.push('"');
+      }
+    }
+
+    self.res.push('>');
+    if !VOID.contains(&tag.name.as_str()) {
+      self.open.push(tag.name);
+    }
+  }
+}
+
+/// Parses the tag at the start of `html`, with its attribute values decoded.
+fn parse_tag(html: &str) -> Option<(Tag, &str)> {
+  let (end, rest) = match html[1..].strip_prefix('/') {
+    Some(rest) => (true, rest),
+    None => (false, &html[1..]),
+  };
+  if !rest.starts_with(|el: char| el.is_ascii_alphabetic()) {
+    return None;
+  }
+
+  let len = rest
+    .find(|el: char| el.is_ascii_whitespace() || el == '/' || el == '>')
+    .unwrap_or(rest.len());
+  let name = rest[..len].to_ascii_lowercase();
+  let mut rest = &rest[len..];
+
+  let mut attributes = Vec::new();
+  loop {
+    let trimmed = rest.trim_start_matches(|el: char| el.is_ascii_whitespace() || el == '/');
+    let skipped = &rest[..rest.len() - trimmed.len()];
+    rest = trimmed;
+    if let Some(after) = rest.strip_prefix('>') {
+      let self_closing = skipped.ends_with('/');
+      return Some((
+        Tag {
+          name,
+          attributes,
+          end,
+          self_closing,
+        },
+        after,
+      ));
+    }
+    if rest.is_empty() {
+      return None;
+    }
+
+    let len = rest
+      .char_indices()
+      .skip(1)
+      .find(|(_, el)| el.is_ascii_whitespace() || ['/', '>', '='].contains(el))
+      .map_or(rest.len(), |(idx, _)| idx);
+    let attribute = rest[..len].to_ascii_lowercase();
+    rest = rest[len..].trim_start();
+
+    let mut value = String::new();
+    if let Some(after) = rest.strip_prefix('=') {
+      rest = after.trim_start();
+      let raw = match rest.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+          let close = rest[1..].find(quote)?;
+          let raw = &rest[1..=close];
+          rest = &rest[close + 2..];
+          raw
+        }
+        _ => {
+          let len = rest
+            .find(|el: char| el.is_ascii_whitespace() || el == '>')
+            .unwrap_or(rest.len());
+          let raw = &rest[..len];
+          rest = &rest[len..];
+          raw
+        }
+      };
+      value = decode_entities(raw);
+    }
+
+    attributes.push((attribute, value));
+  }
+}
+
+/// Skips past the end tag of a raw text element.
+fn skip_raw_text<'a>(html: &'a str, name: &str) -> &'a str {
+  let closing = format!("</{name}");
+  let mut rest = html;
+
+  loop {
+    let Some(idx) = rest.find("</") else {
+      return "";
+    };
+    rest = &rest[idx..];
+
+    let is_end = rest
+      .get(..closing.len())
+      .is_some_and(|el| el.eq_ignore_ascii_case(&closing))
+      && !rest[closing.len()..].starts_with(|el: char| el.is_ascii_alphanumeric());
+    if is_end {
+      return rest.find('>').map_or("", |el| &rest[el + 1..]);
+    }
+    rest = &rest[2..];
+  }
+}
+
+/// Decodes character references, leaving anything that isn't one as it is.
+pub fn decode_entities(text: &str) -> String {
+  let mut res = String::with_capacity(text.len());
+  let mut rest = text;
+
+  while let Some(idx) = rest.find('&') {
+    res.push_str(&rest[..idx]);
+    rest = &rest[idx..];
+
+    match entity(rest) {
+      Some((c, len)) => {
+        res.push(c);
+        rest = &rest[len..];
+      }
+      None => {
+        res.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  res.push_str(rest);
+
+  res
+}
+
+/// The character an entity like `&amp;` or `&#x27;` at the start stands for, and its length.
+fn entity(text: &str) -> Option<(char, usize)> {
+  let (semicolon, _) = text.char_indices().take(34).find(|(_, el)| *el == ';')?;
+  let body = &text[1..semicolon];
+
+  let c = if let Some(hex) = body.strip_prefix("#x").or_else(|| body.strip_prefix("#X")) {
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+  } else if let Some(dec) = body.strip_prefix('#') {
+    char::from_u32(dec.parse().ok()?)?
+  } else {
+    match body {
+      "amp" => '&',
+      "lt" => '<',
+      "gt" => '>',
+      "quot" => '"',
+      "apos" => '\'',
+      "nbsp" => '\u{a0}',
+      "copy" => '©',
+      "reg" => '®',
+      "trade" => '™',
+      "hellip" => '…',
+      "mdash" => '—',
+      "ndash" => '–',
+      "lsquo" => '‘',
+      "rsquo" => '’',
+      "ldquo" => '“',
+      "rdquo" => '”',
+      "laquo" => '«',
+      "raquo" => '»',
+      "times" => '×',
+      "middot" => '·',
+      _ => return None,
+    }
+  };
+
+  // NUL and other controls would be dropped or replaced by a browser anyway
+  if c == '\0' || (c.is_control() && !c.is_ascii_whitespace()) {
+    return None;
+  }
+
+  Some((c, semicolon + 1))
+}
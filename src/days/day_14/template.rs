@@ -0,0 +1,86 @@
+//! The page day 14 wraps its HTML in, sent with a strict Content-Security-Policy.
+
+use axum::{
+  http::{header, HeaderValue},
+  response::{IntoResponse, Response},
+};
+
+use super::escape_html;
+
+pub const DEFAULT_TITLE: &str = "CCH23 Day 14";
+
+#[derive(Debug)]
+pub struct HtmlPage {
+  pub title: String,
+  /// Put in a `<style>` the policy lets through with a nonce.
+  pub css: Option<String>,
+  /// HTML that is already safe to show, one after the other in the body.
+  pub blocks: Vec<String>,
+}
+
+impl HtmlPage {
+  /// With one block and no CSS this is the exact page of the original challenge.
+  pub fn render(&self, nonce: &str) -> String {
+    let title = escape_html(&self.title);
+    let style = self.css.as_ref().map_or_else(String::new, |css| {
+      // `<` means nothing to CSS outside strings, and escaped it can't end the style element
+      let css = css.replace('<', "\\3c ");
+      format!("\n    <style nonce=\"{nonce}\">{css}</style>")
+    });
+    let blocks = self.blocks.join("\n    ");
+
+    format!(
+      r"<html>
+  <head>
+    <title>{title}</title>{style}
+  </head>
+  <body>
+    {blocks}
+  </body>
+</html>"
+    )
+  }
+}
+
+impl IntoResponse for HtmlPage {
+  fn into_response(self) -> Response {
+    let nonce = ulid::Ulid::new().to_string();
+    let style_src = if self.css.is_some() {
+      format!("'nonce-{nonce}'")
+    } else {
+      "'none'".to_string()
+    };
+
+    with_policy(self.render(&nonce), &style_src)
+  }
+}
+
+/// HTML to put in a page of one's own, sent on its own.
+#[derive(Debug)]
+pub struct HtmlFragment(pub String);
+
+impl IntoResponse for HtmlFragment {
+  fn into_response(self) -> Response {
+    with_policy(self.0, "'none'")
+  }
+}
+
+fn with_policy(html: String, style_src: &str) -> Response {
+  // No scripts at all, so even HTML from /14/unsafe can't run any
+  let csp = format!(
+    "default-src 'none'; style-src {style_src}; img-src https: data:; base-uri 'none'; \
+     form-action 'none'; frame-ancestors 'none'"
+  );
+
+  let mut res = html.into_response();
+  let headers = res.headers_mut();
+  let _ = headers.insert(
+    header::CONTENT_TYPE,
+    HeaderValue::from_static("text/html; charset=utf-8"),
+  );
+  if let Ok(csp) = HeaderValue::try_from(csp) {
+    let _ = headers.insert(header::CONTENT_SECURITY_POLICY, csp);
+  }
+
+  res
+}
@@ -0,0 +1,325 @@
+use std::{io, sync::Arc};
+
+use axum::{
+  body::{Body, Bytes, StreamBody},
+  extract::{BodyStream, FromRequest, Path, State},
+  http::{header, Request, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Json, Router,
+};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use super::{AppError, BadRequest};
+use crate::bulk::RecordSplitter;
+use nice::Judgement;
+use policy::{Policies, Policy, Report};
+use strength::{Estimator, Strength};
+
+mod nice;
+mod policy;
+mod strength;
+
+const NDJSON: &str = "application/x-ndjson";
+
+pub fn get_routes() -> Router {
+  Router::new()
+    .route("/15/nice", post(task_1))
+    .route("/15/nice/batch", post(nice_batch))
+    .route("/15/game", post(task_2))
+    .route("/15/policies", get(list_policies))
+    .route("/15/policies/:name", get(get_policy))
+    .route("/15/policies/:name/check", post(check_named))
+    .route("/15/check", post(check_ad_hoc))
+    .route("/15/strength", post(strength))
+    .with_state(PasswordState {
+      policies: Arc::new(Policies::from_env()),
+      estimator: Arc::new(Estimator::from_env()),
+    })
+}
+
+#[derive(Clone, Debug)]
+struct PasswordState {
+  policies: Arc<Policies>,
+  estimator: Arc<Estimator>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SimpleBody {
+  input: String,
+}
+
+async fn task_1(Json(payload): Json<SimpleBody>) -> impl IntoResponse {
+  if nice::judge(&payload.input).nice {
+    (StatusCode::OK, "{\"result\":\"nice\"}".to_string())
+  } else {
+    (
+      StatusCode::BAD_REQUEST,
+      "{\"result\":\"naughty\"}".to_string(),
+    )
+  }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum BatchItem {
+  Text(String),
+  Body(SimpleBody),
+}
+
+impl BatchItem {
+  fn input(&self) -> &str {
+    match self {
+      Self::Text(el) | Self::Body(SimpleBody { input: el }) => el,
+    }
+  }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Explained<'a> {
+  Judged(Judgement<'a>),
+  Invalid { error: String },
+}
+
+#[derive(Serialize, Debug)]
+struct ExplainedLine<'a> {
+  line: u64,
+  #[serde(flatten)]
+  explained: Explained<'a>,
+}
+
+/// Explains the verdict for every input of a JSON list, or line by line for NDJSON, where a
+/// line that can't be read gets an error instead.
+async fn nice_batch(request: Request<Body>) -> Response {
+  let ndjson = request
+    .headers()
+    .get(header::CONTENT_TYPE)
+    .and_then(|el| el.to_str().ok())
+    .is_some_and(|el| el.contains(NDJSON));
+
+  if !ndjson {
+    return match Json::<Vec<BatchItem>>::from_request(request, &()).await {
+      Ok(Json(items)) => Json(
+        items
+          .iter()
+          .map(|el| nice::judge(el.input()))
+          .collect::<Vec<_>>(),
+      )
+      .into_response(),
+      Err(e) => e.into_response(),
+    };
+  }
+
+  let body = match BodyStream::from_request(request, &()).await {
+    Ok(el) => el,
+    Err(e) => return e.into_response(),
+  };
+
+  let lines = stream::unfold(
+    Some((body, RecordSplitter::new(false), 0)),
+    |state| async move {
+      let (mut body, mut splitter, mut line) = state?;
+      let mut out = Vec::new();
+
+      let chunk = match body.next().await {
+        Some(Ok(chunk)) => chunk,
+        Some(Err(e)) => return Some((Err(io::Error::other(e)), None)),
+        None => {
+          if let Some(record) = splitter.finish() {
+            explain_line(&mut out, &mut line, &record);
+          }
+          return Some((Ok(Bytes::from(out)), None));
+        }
+      };
+
+      if let Err(BadRequest(error)) = splitter.push(&chunk) {
+        // The rest of the stream can't be split into lines any more
+        line += 1;
+        write_line(&mut out, line, Explained::Invalid { error });
+        return Some((Ok(Bytes::from(out)), None));
+      }
+      while let Some(record) = splitter.next_record() {
+        explain_line(&mut out, &mut line, &record);
+      }
+
+      Some((Ok(Bytes::from(out)), Some((body, splitter, line))))
+    },
+  );
+
+  ([(header::CONTENT_TYPE, NDJSON)], StreamBody::new(lines)).into_response()
+}
+
+fn explain_line(out: &mut Vec<u8>, line: &mut u64, record: &[u8]) {
+  *line += 1;
+  if record.iter().all(u8::is_ascii_whitespace) {
+    return;
+  }
+
+  match serde_json::from_slice::<BatchItem>(record) {
+    Ok(item) => write_line(out, *line, Explained::Judged(nice::judge(item.input()))),
+    Err(e) => write_line(
+      out,
+      *line,
+      Explained::Invalid {
+        error: e.to_string(),
+      },
+    ),
+  }
+}
+
+fn write_line(out: &mut Vec<u8>, line: u64, explained: Explained) {
+  if serde_json::to_writer(&mut *out, &ExplainedLine { line, explained }).is_ok() {
+    out.push(b'\n');
+  }
+}
+
+#[derive(Serialize, Debug)]
+struct Failure<'a> {
+  rule: &'static str,
+  status: u16,
+  reason: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+struct Verdict<'a> {
+  result: &'static str,
+  reason: &'a str,
+  /// Only there when all failures are reported.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  failures: Option<Vec<Failure<'a>>>,
+}
+
+/// Nice, or naughty with the status and reason of the first rule that failed.
+fn judge(policy: &Policy, input: &str, report: Option<Report>) -> Response {
+  let report = report.unwrap_or(policy.report);
+  let failed = policy.failures(input, report);
+
+  let Some(first) = failed.first() else {
+    let verdict = Verdict {
+      result: "nice",
+      reason: &policy.nice_message,
+      failures: (report == Report::All).then(Vec::new),
+    };
+    return (StatusCode::OK, Json(verdict)).into_response();
+  };
+
+  let status = StatusCode::from_u16(first.status).unwrap_or(StatusCode::BAD_REQUEST);
+  let failures = (report == Report::All).then(|| {
+    failed
+      .iter()
+      .map(|el| Failure {
+        rule: el.check.name(),
+        status: el.status,
+        reason: &el.message,
+      })
+      .collect()
+  });
+  let verdict = Verdict {
+    result: "naughty",
+    reason: &first.message,
+    failures,
+  };
+
+  (status, Json(verdict)).into_response()
+}
+
+async fn task_2(
+  State(PasswordState { policies, .. }): State<PasswordState>,
+  Json(payload): Json<SimpleBody>,
+) -> Response {
+  match policies.0.get("game") {
+    Some(policy) => judge(policy, &payload.input, None),
+    None => StatusCode::NOT_FOUND.into_response(),
+  }
+}
+
+async fn list_policies(
+  State(PasswordState { policies, .. }): State<PasswordState>,
+) -> Json<Vec<String>> {
+  Json(policies.0.keys().cloned().collect())
+}
+
+async fn get_policy(
+  Path(name): Path<String>,
+  State(PasswordState { policies, .. }): State<PasswordState>,
+) -> Response {
+  match policies.0.get(&name) {
+    Some(policy) => Json(policy).into_response(),
+    None => StatusCode::NOT_FOUND.into_response(),
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct CheckBody {
+  input: String,
+  /// The policy's own setting when left out.
+  report: Option<Report>,
+}
+
+async fn check_named(
+  Path(name): Path<String>,
+  State(PasswordState { policies, .. }): State<PasswordState>,
+  Json(payload): Json<CheckBody>,
+) -> Response {
+  match policies.0.get(&name) {
+    Some(policy) => judge(policy, &payload.input, payload.report),
+    None => StatusCode::NOT_FOUND.into_response(),
+  }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum PolicySource {
+  Json(Policy),
+  /// TOML, or JSON in a string.
+  Text(String),
+}
+
+#[derive(Deserialize, Debug)]
+struct AdHocCheck {
+  input: String,
+  policy: PolicySource,
+  report: Option<Report>,
+}
+
+/// Checks against a policy sent along with the password.
+async fn check_ad_hoc(Json(payload): Json<AdHocCheck>) -> Result<Response, AppError> {
+  let policy = match payload.policy {
+    PolicySource::Json(el) => el,
+    PolicySource::Text(el) => policy::parse(&el).map_err(BadRequest)?,
+  };
+  policy.validate().map_err(BadRequest)?;
+
+  Ok(judge(&policy, &payload.input, payload.report))
+}
+
+#[derive(Deserialize, Debug)]
+struct StrengthBody {
+  input: String,
+  /// Words an attacker would try first, like the user's name or email.
+  #[serde(default)]
+  user_inputs: Vec<String>,
+}
+
+async fn strength(
+  State(PasswordState { estimator, .. }): State<PasswordState>,
+  Json(payload): Json<StrengthBody>,
+) -> Result<Json<Strength>, AppError> {
+  if payload.input.chars().count() > strength::MAX_LENGTH {
+    return Err(
+      BadRequest(format!(
+        "Passwords are only analysed up to {} characters",
+        strength::MAX_LENGTH
+      ))
+      .into(),
+    );
+  }
+
+  Ok(Json(
+    estimator
+      .estimate(&payload.input, &payload.user_inputs)
+      .await?,
+  ))
+}
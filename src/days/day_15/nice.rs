@@ -0,0 +1,100 @@
+//! The naughty or nice rules of the original day 15, explained. They look at grapheme clusters
+//! rather than bytes, so accented vowels count and `éé` is a double letter like `ee`.
+
+use serde::{Serialize, Serializer};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+const VOWELS: &str = "aeiouy";
+const MIN_VOWELS: usize = 3;
+const FORBIDDEN_PAIRS: [&str; 4] = ["ab", "cd", "pq", "xy"];
+
+/// Where a rule matched, as a byte offset into the input.
+#[derive(Serialize, Debug)]
+pub struct Span<'a> {
+  pub offset: usize,
+  pub text: &'a str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RuleCheck<'a> {
+  pub rule: &'static str,
+  pub passed: bool,
+  /// What the rule looked for: the vowels, the doubled letters or the forbidden pairs.
+  pub matches: Vec<Span<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Judgement<'a> {
+  #[serde(rename = "result", serialize_with = "verdict")]
+  pub nice: bool,
+  pub rules: Vec<RuleCheck<'a>>,
+}
+
+fn verdict<S: Serializer>(nice: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+  serializer.serialize_str(if *nice { "nice" } else { "naughty" })
+}
+
+/// A lowercase vowel, with any accents on it.
+fn is_vowel(grapheme: &str) -> bool {
+  grapheme
+    .nfd()
+    .next()
+    .is_some_and(|base| VOWELS.contains(base))
+}
+
+fn same_letter(a: &str, b: &str) -> bool {
+  a.chars().next().is_some_and(char::is_alphabetic) && a.nfc().eq(b.nfc())
+}
+
+pub fn judge(input: &str) -> Judgement<'_> {
+  let graphemes = input.grapheme_indices(true).collect::<Vec<_>>();
+  let span = |offset: usize, len: usize| Span {
+    offset,
+    text: &input[offset..offset + len],
+  };
+  let pairs = || graphemes.windows(2).map(|el| (el[0].0, el[0].1, el[1].1));
+
+  let vowels = graphemes
+    .iter()
+    .filter(|(_, el)| is_vowel(el))
+    .map(|(offset, el)| span(*offset, el.len()))
+    .collect::<Vec<_>>();
+
+  let doubles = pairs()
+    .filter(|(_, a, b)| same_letter(a, b))
+    .map(|(offset, a, b)| span(offset, a.len() + b.len()))
+    .collect::<Vec<_>>();
+
+  let forbidden = pairs()
+    .filter(|(_, a, b)| {
+      FORBIDDEN_PAIRS
+        .iter()
+        .any(|el| el.strip_prefix(a) == Some(b))
+    })
+    .map(|(offset, a, b)| span(offset, a.len() + b.len()))
+    .collect::<Vec<_>>();
+
+  let rules = vec![
+    RuleCheck {
+      rule: "three_vowels",
+      passed: vowels.len() >= MIN_VOWELS,
+      matches: vowels,
+    },
+    RuleCheck {
+      rule: "double_letter",
+      passed: !doubles.is_empty(),
+      matches: doubles,
+    },
+    RuleCheck {
+      rule: "no_forbidden_pair",
+      passed: forbidden.is_empty(),
+      matches: forbidden,
+    },
+  ];
+
+  Judgement {
+    nice: rules.iter().all(|el| el.passed),
+    rules,
+  }
+}
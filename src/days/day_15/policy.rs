@@ -0,0 +1,415 @@
+//! Password policies made of rules, written as JSON or TOML like
+//!
+//! ```toml
+//! report = "all"
+//!
+//! [[rules]]
+//! type = "min_length"
+//! min = 12
+//! status = 400
+//! message = "at least 12 chars"
+//! ```
+
+use std::collections::BTreeMap;
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Report {
+  /// Stop at the first rule that fails.
+  #[default]
+  First,
+  All,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+  pub rules: Vec<Rule>,
+  #[serde(default)]
+  pub report: Report,
+  /// The reason given when every rule passes.
+  #[serde(default = "nice_message")]
+  pub nice_message: String,
+}
+
+fn nice_message() -> String {
+  "that's a nice password".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Rule {
+  #[serde(flatten)]
+  pub check: Check,
+  /// Sent when this is the first rule that fails.
+  #[serde(default = "bad_request")]
+  pub status: u16,
+  pub message: String,
+}
+
+const fn bad_request() -> u16 {
+  400
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+  Bytes,
+  #[default]
+  Chars,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CharClass {
+  Uppercase,
+  Lowercase,
+  Letter,
+  AsciiLetter,
+  /// Only ASCII digits, like everywhere else in the rules.
+  Digit,
+  /// Anything printable that isn't a letter or a digit.
+  Symbol,
+  Whitespace,
+}
+
+impl CharClass {
+  fn contains(self, c: char) -> bool {
+    match self {
+      Self::Uppercase => c.is_uppercase(),
+      Self::Lowercase => c.is_lowercase(),
+      Self::Letter => c.is_alphabetic(),
+      Self::AsciiLetter => c.is_ascii_alphabetic(),
+      Self::Digit => c.is_ascii_digit(),
+      Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control(),
+      Self::Whitespace => c.is_whitespace(),
+    }
+  }
+}
+
+const fn ascii_letter() -> CharClass {
+  CharClass::AsciiLetter
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Summing {
+  /// Runs of digits count as one number, so `20a23` adds up to 43.
+  #[default]
+  Numbers,
+  Digits,
+}
+
+const fn one() -> usize {
+  1
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Check {
+  MinLength {
+    min: usize,
+    #[serde(default)]
+    unit: Unit,
+  },
+  /// Characters from at least `min` of the classes, all of them when left out.
+  CharacterClasses {
+    classes: Vec<CharClass>,
+    min: Option<usize>,
+  },
+  DigitCount {
+    min: usize,
+  },
+  DigitSum {
+    equals: u64,
+    #[serde(default)]
+    by: Summing,
+  },
+  /// The letters in this order, with anything in between. With `exactly_once` there are only
+  /// as many of them as in `letters`, none after a letter that comes later in it, which is how
+  /// the game always counted.
+  OrderedSubsequence {
+    letters: String,
+    #[serde(default)]
+    exactly_once: bool,
+  },
+  /// Two of the same character around another one, like `aba`. In bytes the one in the middle
+  /// can be half of a character, so `aéa` has none.
+  Sandwich {
+    #[serde(default = "ascii_letter")]
+    class: CharClass,
+    #[serde(default)]
+    unit: Unit,
+  },
+  UnicodeRange {
+    from: char,
+    to: char,
+    #[serde(default = "one")]
+    min: usize,
+  },
+  Emoji {
+    #[serde(default = "one")]
+    min: usize,
+  },
+  /// The hex SHA-256 of the password ends with this.
+  HashSuffix {
+    suffix: String,
+  },
+}
+
+impl Check {
+  pub const fn name(&self) -> &'static str {
+    match self {
+      Self::MinLength { .. } => "min_length",
+      Self::CharacterClasses { .. } => "character_classes",
+      Self::DigitCount { .. } => "digit_count",
+      Self::DigitSum { .. } => "digit_sum",
+      Self::OrderedSubsequence { .. } => "ordered_subsequence",
+      Self::Sandwich { .. } => "sandwich",
+      Self::UnicodeRange { .. } => "unicode_range",
+      Self::Emoji { .. } => "emoji",
+      Self::HashSuffix { .. } => "hash_suffix",
+    }
+  }
+
+  fn validate(&self) -> Result<(), String> {
+    match self {
+      Self::CharacterClasses { classes, min } => {
+        if classes.is_empty() {
+          return Err("character_classes needs at least one class".to_string());
+        }
+        if min.is_some_and(|el| el > classes.len()) {
+          return Err("character_classes can't need more classes than it has".to_string());
+        }
+      }
+      Self::OrderedSubsequence { letters, .. } if letters.is_empty() => {
+        return Err("ordered_subsequence needs letters".to_string());
+      }
+      Self::UnicodeRange { from, to, .. } if from > to => {
+        return Err("unicode_range starts after it ends".to_string());
+      }
+      _ => {}
+    }
+
+    Ok(())
+  }
+
+  pub fn passes(&self, input: &str) -> bool {
+    match self {
+      Self::MinLength { min, unit } => match unit {
+        Unit::Bytes => input.len() >= *min,
+        Unit::Chars => input.chars().count() >= *min,
+      },
+      Self::CharacterClasses { classes, min } => {
+        let present = classes
+          .iter()
+          .filter(|class| input.chars().any(|c| class.contains(c)))
+          .count();
+        present >= min.unwrap_or(classes.len())
+      }
+      Self::DigitCount { min } => input.chars().filter(char::is_ascii_digit).count() >= *min,
+      Self::DigitSum { equals, by } => digit_sum(input, *by) == Some(*equals),
+      Self::OrderedSubsequence {
+        letters,
+        exactly_once: true,
+      } => {
+        let ranks = input
+          .chars()
+          .filter_map(|c| letters.chars().position(|el| el == c))
+          .collect::<Vec<_>>();
+        ranks.len() == letters.chars().count() && ranks.windows(2).all(|el| el[0] <= el[1])
+      }
+      Self::OrderedSubsequence { letters, .. } => {
+        let mut input = input.chars();
+        letters.chars().all(|letter| input.any(|c| c == letter))
+      }
+      Self::Sandwich { class, unit } => match unit {
+        Unit::Bytes => input
+          .as_bytes()
+          .windows(3)
+          .any(|el| el[0] == el[2] && el[0].is_ascii() && class.contains(char::from(el[0]))),
+        Unit::Chars => input
+          .chars()
+          .collect::<Vec<_>>()
+          .windows(3)
+          .any(|el| el[0] == el[2] && class.contains(el[0])),
+      },
+      Self::UnicodeRange { from, to, min } => {
+        input.chars().filter(|c| (from..=to).contains(&c)).count() >= *min
+      }
+      Self::Emoji { min } => {
+        input
+          .graphemes(true)
+          .filter(|el| emojis::get(el).is_some())
+          .count()
+          >= *min
+      }
+      Self::HashSuffix { suffix } => sha256::digest(input).ends_with(&suffix.to_ascii_lowercase()),
+    }
+  }
+}
+
+/// `None` when the sum doesn't fit, which then can't equal anything.
+fn digit_sum(input: &str, by: Summing) -> Option<u64> {
+  match by {
+    Summing::Digits => Some(
+      input
+        .chars()
+        .filter_map(|c| c.to_digit(10).filter(|_| c.is_ascii_digit()))
+        .map(u64::from)
+        .sum(),
+    ),
+    Summing::Numbers => input
+      .split(|c: char| !c.is_ascii_digit())
+      .filter(|el| !el.is_empty())
+      .try_fold(0_u64, |sum, el| sum.checked_add(el.parse().ok()?)),
+  }
+}
+
+impl Policy {
+  pub fn validate(&self) -> Result<(), String> {
+    for rule in &self.rules {
+      if !(400..600).contains(&rule.status) || StatusCode::from_u16(rule.status).is_err() {
+        return Err(format!(
+          "{} has status {}, which is not an error",
+          rule.check.name(),
+          rule.status
+        ));
+      }
+      rule.check.validate()?;
+    }
+
+    Ok(())
+  }
+
+  /// The rules the password fails, only the first one unless all are reported.
+  pub fn failures(&self, input: &str, report: Report) -> Vec<&Rule> {
+    let failed = self.rules.iter().filter(|el| !el.check.passes(input));
+    match report {
+      Report::First => failed.take(1).collect(),
+      Report::All => failed.collect(),
+    }
+  }
+
+  /// The rules of the original day 15 game, checked in the same order.
+  pub fn game() -> Self {
+    let rule = |check, status, message: &str| Rule {
+      check,
+      status,
+      message: message.to_string(),
+    };
+
+    Self {
+      rules: vec![
+        rule(
+          Check::MinLength {
+            min: 8,
+            unit: Unit::Bytes,
+          },
+          400,
+          "8 chars",
+        ),
+        rule(
+          Check::CharacterClasses {
+            classes: vec![CharClass::Uppercase, CharClass::Lowercase, CharClass::Digit],
+            min: None,
+          },
+          400,
+          "more types of chars",
+        ),
+        rule(Check::DigitCount { min: 5 }, 400, "55555"),
+        rule(
+          Check::DigitSum {
+            equals: 2023,
+            by: Summing::Numbers,
+          },
+          400,
+          "math is hard",
+        ),
+        rule(
+          Check::OrderedSubsequence {
+            letters: "joy".to_string(),
+            exactly_once: true,
+          },
+          406,
+          "not joyful enough",
+        ),
+        rule(
+          Check::Sandwich {
+            class: CharClass::AsciiLetter,
+            unit: Unit::Bytes,
+          },
+          451,
+          "illegal: no sandwich",
+        ),
+        rule(
+          Check::UnicodeRange {
+            from: '\u{2980}',
+            to: '\u{2BFF}',
+            min: 1,
+          },
+          416,
+          "outranged",
+        ),
+        rule(Check::Emoji { min: 1 }, 426, "😳"),
+        rule(
+          Check::HashSuffix {
+            suffix: "a".to_string(),
+          },
+          418,
+          "not a coffee brewer",
+        ),
+      ],
+      report: Report::First,
+      nice_message: nice_message(),
+    }
+  }
+}
+
+/// Parses policies written as JSON, or as TOML when it isn't JSON.
+pub fn parse<T: serde::de::DeserializeOwned>(source: &str) -> Result<T, String> {
+  if source.trim_start().starts_with('{') {
+    serde_json::from_str(source).map_err(|e| format!("Invalid JSON policy: {e}"))
+  } else {
+    toml::from_str(source).map_err(|e| format!("Invalid TOML policy: {e}"))
+  }
+}
+
+/// The policies that can be checked against by name.
+#[derive(Debug)]
+pub struct Policies(pub BTreeMap<String, Policy>);
+
+impl Policies {
+  /// The `game` preset, and the policies in `PASSWORD_POLICIES` as a JSON or TOML table of
+  /// policies by name.
+  pub fn from_env() -> Self {
+    let mut policies = std::env::var("PASSWORD_POLICIES")
+      .ok()
+      .and_then(|el| {
+        parse::<BTreeMap<String, Policy>>(&el)
+          .map_err(|e| warn!("Ignoring invalid PASSWORD_POLICIES: {:?}", e))
+          .ok()
+      })
+      .unwrap_or_default();
+
+    policies.retain(|name, policy| match policy.validate() {
+      Ok(()) => true,
+      Err(e) => {
+        warn!("Ignoring password policy {}: {}", name, e);
+        false
+      }
+    });
+    if policies
+      .insert("game".to_string(), Policy::game())
+      .is_some()
+    {
+      warn!("Ignoring password policy game, the name is taken by the preset");
+    }
+
+    Self(policies)
+  }
+}
@@ -0,0 +1,931 @@
+//! Estimates how many guesses a password takes, much like zxcvbn: find the patterns an attacker
+//! would try first, then the cheapest way to cover the whole password with them.
+//!
+//! Unlike zxcvbn, the dictionary is only a few hundred of the most common passwords. Ordinary words
+//! and names aren't in it, so a password made of them is weaker than estimated here.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  fmt::Write,
+  path::PathBuf,
+};
+
+use chrono::{Datelike, Utc};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use crate::days::AppError;
+
+/// Longer passwords aren't analysed, there is no point and it only gets slower.
+pub const MAX_LENGTH: usize = 256;
+
+/// Ranked by how common they are, most common first.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Keyboard rows, lined up so the keys above the one at `(row, col)` are at `col` and `col + 1`.
+const KEYBOARD: [&str; 4] = ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'", "zxcvbnm,./"];
+const SHIFTED: &str = "!@#$%^&*()_+{}:\"<>?";
+const UNSHIFTED: &str = "1234567890-=[];',./";
+/// How many keys a walk can start on, and how many neighbours a key has on average.
+const KEYBOARD_STARTS: f64 = 47.0;
+const KEYBOARD_DEGREE: f64 = 4.6;
+
+/// What the digits and symbols in l33t speak stand for, with both readings of the ambiguous ones.
+const L33T: [&[(char, char)]; 2] = [
+  &[
+    ('4', 'a'),
+    ('@', 'a'),
+    ('8', 'b'),
+    ('(', 'c'),
+    ('3', 'e'),
+    ('6', 'g'),
+    ('9', 'g'),
+    ('1', 'i'),
+    ('!', 'i'),
+    ('|', 'i'),
+    ('0', 'o'),
+    ('$', 's'),
+    ('5', 's'),
+    ('7', 't'),
+    ('+', 't'),
+    ('2', 'z'),
+  ],
+  &[('1', 'l'), ('|', 'l')],
+];
+
+/// Guesses per second for the usual ways of attacking a password.
+const SCENARIOS: [(&str, f64); 4] = [
+  ("online_throttled", 100.0 / 3600.0),
+  ("online_unthrottled", 10.0),
+  ("offline_slow_hash", 1e4),
+  ("offline_fast_hash", 1e10),
+];
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "pattern", rename_all = "snake_case")]
+pub enum Pattern {
+  Dictionary {
+    word: String,
+    rank: usize,
+    /// Substituted characters and the letters they stand for.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    l33t: BTreeMap<char, char>,
+    reversed: bool,
+    /// One of the words sent along with the password, like the user's name.
+    user_input: bool,
+  },
+  Spatial {
+    turns: usize,
+    shifted: usize,
+  },
+  Repeat {
+    base: String,
+    count: usize,
+  },
+  Sequence {
+    ascending: bool,
+  },
+  Date {
+    year: i32,
+    month: u32,
+    day: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    separator: Option<char>,
+  },
+  Year {
+    year: i32,
+  },
+  Bruteforce,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Match {
+  #[serde(flatten)]
+  pub pattern: Pattern,
+  pub token: String,
+  /// In characters, the end exclusive.
+  pub start: usize,
+  pub end: usize,
+  pub guesses_log10: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CrackTime {
+  /// Left out when it doesn't fit in a float, see `seconds_log10`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  seconds: Option<f64>,
+  seconds_log10: f64,
+  display: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Breach {
+  checked: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  count: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Strength {
+  length: usize,
+  /// Left out when it doesn't fit in a float, long passwords only have `guesses_log10`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  guesses: Option<f64>,
+  guesses_log10: f64,
+  entropy_bits: f64,
+  /// From 0, guessed right away, to 4, very unlikely to be guessed.
+  score: u8,
+  crack_times: BTreeMap<&'static str, CrackTime>,
+  /// The cheapest way found to guess the password, in order.
+  patterns: Vec<Match>,
+  /// How many common passwords dictionary matches were looked up in, no ordinary words among them.
+  dictionary_size: usize,
+  breach: Breach,
+  suggestions: Vec<String>,
+}
+
+/// Where to look up passwords seen in breaches, set with `PWNED_PASSWORDS`.
+#[derive(Debug)]
+enum Breaches {
+  Unavailable,
+  /// A file of `HASH:COUNT` lines, kept by the first five characters of the hash.
+  Loaded(HashMap<String, Vec<(String, u64)>>),
+  /// A file of `SUFFIX:COUNT` lines for each prefix, named like `21BD1.txt`, read when needed.
+  Directory(PathBuf),
+}
+
+impl Breaches {
+  fn from_env() -> Self {
+    let Ok(path) = std::env::var("PWNED_PASSWORDS") else {
+      return Self::Unavailable;
+    };
+
+    let path = PathBuf::from(path);
+    if path.is_dir() {
+      return Self::Directory(path);
+    }
+
+    match std::fs::read_to_string(&path) {
+      Ok(text) => {
+        let mut hashes = HashMap::<String, Vec<(String, u64)>>::new();
+        for (hash, count) in text.lines().filter_map(|el| hash_line(el, 40)) {
+          hashes
+            .entry(hash[..5].to_string())
+            .or_default()
+            .push((hash[5..].to_string(), count));
+        }
+        Self::Loaded(hashes)
+      }
+      Err(e) => {
+        warn!("Ignoring PWNED_PASSWORDS, can't read it: {:?}", e);
+        Self::Unavailable
+      }
+    }
+  }
+
+  /// How often the password was seen, or `None` when there is nothing to look it up in.
+  async fn count(&self, password: &str) -> Result<Option<u64>, AppError> {
+    let hash =
+      Sha1::digest(password.as_bytes())
+        .iter()
+        .fold(String::with_capacity(40), |mut res, el| {
+          let _ = write!(res, "{el:02X}");
+          res
+        });
+    let (prefix, suffix) = hash.split_at(5);
+
+    // Only the prefix is used to pick what to search, like with the k-anonymity range API
+    let count = match self {
+      Self::Unavailable => return Ok(None),
+      Self::Loaded(hashes) => hashes
+        .get(prefix)
+        .and_then(|el| el.iter().find(|(el, _)| el == suffix))
+        .map_or(0, |(_, count)| *count),
+      Self::Directory(dir) => {
+        let files = [dir.join(format!("{prefix}.txt")), dir.join(prefix)];
+        let suffix = suffix.to_string();
+        tokio::task::spawn_blocking(move || {
+          let Some(text) = files.iter().find_map(|el| std::fs::read_to_string(el).ok()) else {
+            return 0;
+          };
+          text
+            .lines()
+            .filter_map(|el| hash_line(el, 35))
+            .find(|(el, _)| *el == suffix)
+            .map_or(0, |(_, count)| count)
+        })
+        .await?
+      }
+    };
+
+    Ok(Some(count))
+  }
+}
+
+/// A hex hash of the given length with an optional `:COUNT` after it.
+fn hash_line(line: &str, len: usize) -> Option<(String, u64)> {
+  let (hash, count) = line
+    .trim()
+    .split_once(':')
+    .unwrap_or_else(|| (line.trim(), "1"));
+  if hash.len() != len || !hash.bytes().all(|el| el.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  Some((hash.to_ascii_uppercase(), count.trim().parse().ok()?))
+}
+
+#[derive(Debug)]
+pub struct Estimator {
+  ranks: HashMap<String, usize>,
+  longest_word: usize,
+  breaches: Breaches,
+}
+
+impl Estimator {
+  pub fn from_env() -> Self {
+    let mut ranks = HashMap::new();
+    for (idx, word) in COMMON_PASSWORDS.lines().enumerate() {
+      let _ = ranks.entry(word.to_lowercase()).or_insert(idx + 1);
+    }
+    let longest_word = ranks.keys().map(|el| el.chars().count()).max().unwrap_or(0);
+
+    Self {
+      ranks,
+      longest_word,
+      breaches: Breaches::from_env(),
+    }
+  }
+
+  pub async fn estimate(
+    &self,
+    password: &str,
+    user_inputs: &[String],
+  ) -> Result<Strength, AppError> {
+    let chars = password.chars().collect::<Vec<_>>();
+    let year = Utc::now().year();
+
+    let mut matches = self.dictionary(&chars, user_inputs);
+    matches.extend(spatial(&chars));
+    matches.extend(repeats(&chars));
+    matches.extend(sequences(&chars));
+    matches.extend(dates(&chars, year));
+
+    let patterns = cheapest_cover(&chars, matches);
+    let guesses_log10 = patterns.iter().fold(0.0, |sum, el| sum + el.guesses_log10);
+    let score = match guesses_log10 {
+      el if el < 3.0 => 0,
+      el if el < 6.0 => 1,
+      el if el < 8.0 => 2,
+      el if el < 10.0 => 3,
+      _ => 4,
+    };
+
+    let count = self.breaches.count(password).await?;
+    let suggestions = suggestions(&chars, &patterns, score, count.unwrap_or(0));
+
+    let crack_times = SCENARIOS
+      .iter()
+      .map(|(name, rate)| {
+        let seconds_log10 = guesses_log10 - rate.log10();
+        let seconds = 10_f64.powf(seconds_log10);
+        (
+          *name,
+          CrackTime {
+            seconds: seconds.is_finite().then_some(seconds),
+            seconds_log10,
+            display: display_time(seconds),
+          },
+        )
+      })
+      .collect();
+
+    Ok(Strength {
+      length: chars.len(),
+      guesses: Some(10_f64.powf(guesses_log10)).filter(|el| el.is_finite()),
+      guesses_log10,
+      entropy_bits: guesses_log10 * 10_f64.log2(),
+      score,
+      crack_times,
+      patterns,
+      dictionary_size: self.ranks.len(),
+      breach: Breach {
+        checked: count.is_some(),
+        count,
+      },
+      suggestions,
+    })
+  }
+
+  /// Common passwords and the user's own words, also reversed and in l33t speak.
+  fn dictionary(&self, chars: &[char], user_inputs: &[String]) -> Vec<Match> {
+    let user_ranks = user_inputs
+      .iter()
+      .enumerate()
+      .map(|(idx, el)| (el.to_lowercase(), idx + 1))
+      .collect::<HashMap<_, _>>();
+    let longest = user_ranks
+      .keys()
+      .map(|el| el.chars().count())
+      .max()
+      .unwrap_or(0)
+      .max(self.longest_word);
+    let lookup = |word: &str| {
+      user_ranks
+        .get(word)
+        .map(|el| (*el, true))
+        .or_else(|| self.ranks.get(word).map(|el| (*el, false)))
+    };
+
+    let lower = chars
+      .iter()
+      .flat_map(|el| el.to_lowercase())
+      .collect::<Vec<_>>();
+    // Lowercasing can change the number of characters, the offsets would be off then
+    let lower = if lower.len() == chars.len() {
+      lower
+    } else {
+      chars.to_vec()
+    };
+
+    let mut res = Vec::new();
+    for start in 0..chars.len() {
+      for end in start + 3..=chars.len().min(start + longest) {
+        let original = &chars[start..end];
+        let token = &lower[start..end];
+        let word = token.iter().collect::<String>();
+        let reversed = token.iter().rev().collect::<String>();
+
+        let mut found = vec![];
+        if let Some((rank, user_input)) = lookup(&word) {
+          found.push((word.clone(), rank, user_input, BTreeMap::new(), false));
+        }
+        if reversed != word {
+          if let Some((rank, user_input)) = lookup(&reversed) {
+            found.push((reversed, rank, user_input, BTreeMap::new(), true));
+          }
+        }
+        for table in L33T {
+          let subs = token
+            .iter()
+            .filter_map(|c| table.iter().find(|(el, _)| el == c).copied())
+            .collect::<BTreeMap<_, _>>();
+          if subs.is_empty() {
+            continue;
+          }
+          let plain = token
+            .iter()
+            .map(|el| subs.get(el).copied().unwrap_or(*el))
+            .collect::<String>();
+          if let Some((rank, user_input)) = lookup(&plain) {
+            found.push((plain, rank, user_input, subs, false));
+          }
+        }
+
+        for (word, rank, user_input, l33t, reversed) in found {
+          #[allow(clippy::cast_precision_loss)]
+          let mut guesses = (rank as f64).log10() + uppercase_variations(original).log10();
+          guesses += l33t_variations(token, &l33t).log10();
+          if reversed {
+            guesses += 2_f64.log10();
+          }
+
+          res.push(Match {
+            pattern: Pattern::Dictionary {
+              word,
+              rank,
+              l33t,
+              reversed,
+              user_input,
+            },
+            token: original.iter().collect(),
+            start,
+            end,
+            guesses_log10: guesses,
+          });
+        }
+      }
+    }
+
+    res
+  }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn binomial(n: usize, k: usize) -> f64 {
+  (0..k).fold(1.0, |res, i| res * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// How many ways of capitalizing the word an attacker would have to try.
+fn uppercase_variations(token: &[char]) -> f64 {
+  let upper = token.iter().filter(|el| el.is_uppercase()).count();
+  let lower = token.iter().filter(|el| el.is_lowercase()).count();
+  if upper == 0 {
+    return 1.0;
+  }
+
+  let first_only = upper == 1 && token.first().is_some_and(|el| el.is_uppercase());
+  let last_only = upper == 1 && token.last().is_some_and(|el| el.is_uppercase());
+  if first_only || last_only || lower == 0 {
+    return 2.0;
+  }
+
+  (1..=upper.min(lower))
+    .map(|el| binomial(upper + lower, el))
+    .sum()
+}
+
+fn l33t_variations(token: &[char], subs: &BTreeMap<char, char>) -> f64 {
+  subs
+    .iter()
+    .map(|(subbed, letter)| {
+      let subbed = token.iter().filter(|el| *el == subbed).count();
+      let plain = token.iter().filter(|el| *el == letter).count();
+      if plain == 0 {
+        2.0
+      } else {
+        (1..=plain.min(subbed))
+          .map(|el| binomial(plain + subbed, el))
+          .sum()
+      }
+    })
+    .product()
+}
+
+/// Where the key is on the keyboard, and whether shift is needed for it.
+fn key(c: char) -> Option<(usize, usize, bool)> {
+  let (c, shifted) = match SHIFTED.find(c) {
+    Some(idx) => (UNSHIFTED.as_bytes()[idx].into(), true),
+    None if c.is_ascii_uppercase() => (c.to_ascii_lowercase(), true),
+    None => (c, false),
+  };
+
+  KEYBOARD
+    .iter()
+    .enumerate()
+    .find_map(|(row, keys)| keys.find(c).map(|col| (row, col, shifted)))
+}
+
+/// The direction from one key to a neighbouring one.
+#[allow(clippy::cast_possible_wrap)]
+fn direction(from: (usize, usize, bool), to: (usize, usize, bool)) -> Option<(isize, isize)> {
+  let step = (
+    to.0 as isize - from.0 as isize,
+    to.1 as isize - from.1 as isize,
+  );
+  matches!(step, (0, -1 | 1) | (-1, 0 | 1) | (1, -1 | 0)).then_some(step)
+}
+
+/// Walks across neighbouring keys, like `qwerty` or `zxcvfr`.
+fn spatial(chars: &[char]) -> Vec<Match> {
+  let mut res = Vec::new();
+  let mut start = 0;
+
+  while start + 2 < chars.len() {
+    let Some(first) = key(chars[start]) else {
+      start += 1;
+      continue;
+    };
+
+    let mut end = start + 1;
+    let mut turns = 0;
+    let mut shifted = usize::from(first.2);
+    let mut last = None;
+    let mut from = first;
+    while let Some(to) = chars.get(end).and_then(|el| key(*el)) {
+      let Some(step) = direction(from, to) else {
+        break;
+      };
+      if last != Some(step) {
+        turns += 1;
+        last = Some(step);
+      }
+      shifted += usize::from(to.2);
+      from = to;
+      end += 1;
+    }
+
+    if end - start < 3 {
+      start += 1;
+      continue;
+    }
+
+    let len = end - start;
+    let mut guesses = 0.0;
+    for i in 2..=len {
+      for j in 1..=turns.min(i - 1) {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let degree = KEYBOARD_DEGREE.powi(j as i32);
+        guesses += binomial(i - 1, j - 1) * KEYBOARD_STARTS * degree;
+      }
+    }
+    if shifted > 0 {
+      let unshifted = len - shifted;
+      guesses *= if unshifted == 0 {
+        2.0
+      } else {
+        (1..=shifted.min(unshifted))
+          .map(|el| binomial(len, el))
+          .sum()
+      };
+    }
+
+    res.push(Match {
+      pattern: Pattern::Spatial { turns, shifted },
+      token: chars[start..end].iter().collect(),
+      start,
+      end,
+      guesses_log10: guesses.log10(),
+    });
+    start = end;
+  }
+
+  res
+}
+
+/// Characters a bruteforce attack has to try for each position in the password.
+fn cardinality(chars: &[char]) -> f64 {
+  let mut res = 0.0;
+  if chars.iter().any(char::is_ascii_lowercase) {
+    res += 26.0;
+  }
+  if chars.iter().any(char::is_ascii_uppercase) {
+    res += 26.0;
+  }
+  if chars.iter().any(char::is_ascii_digit) {
+    res += 10.0;
+  }
+  if chars.iter().any(char::is_ascii_punctuation) || chars.contains(&' ') {
+    res += 33.0;
+  }
+  if chars.iter().any(|el| !el.is_ascii()) {
+    res += 100.0;
+  }
+
+  f64::max(res, 10.0)
+}
+
+/// The same character or run of characters over and over, like `aaa` or `abcabc`.
+fn repeats(chars: &[char]) -> Vec<Match> {
+  let mut res = Vec::new();
+  let mut start = 0;
+
+  while start < chars.len() {
+    let mut best = None::<(usize, usize)>;
+    for unit in 1..=(chars.len() - start) / 2 {
+      let base = &chars[start..start + unit];
+      let count = chars[start..]
+        .chunks_exact(unit)
+        .take_while(|el| *el == base)
+        .count();
+      if count >= 2 && (unit > 1 || count >= 3) && best.is_none_or(|(u, c)| unit * count > u * c) {
+        best = Some((unit, count));
+      }
+    }
+
+    let Some((unit, count)) = best else {
+      start += 1;
+      continue;
+    };
+
+    let base = &chars[start..start + unit];
+    #[allow(clippy::cast_precision_loss)]
+    let guesses = cardinality(base)
+      .log10()
+      .mul_add(unit as f64, (count as f64).log10());
+    res.push(Match {
+      pattern: Pattern::Repeat {
+        base: base.iter().collect(),
+        count,
+      },
+      token: chars[start..start + unit * count].iter().collect(),
+      start,
+      end: start + unit * count,
+      guesses_log10: guesses,
+    });
+    start += unit * count;
+  }
+
+  res
+}
+
+/// Runs of consecutive characters, like `abcd` or `9876`.
+fn sequences(chars: &[char]) -> Vec<Match> {
+  let mut res = Vec::new();
+  let mut start = 0;
+
+  while start + 2 < chars.len() {
+    let delta = i64::from(u32::from(chars[start + 1])) - i64::from(u32::from(chars[start]));
+    let mut end = start + 2;
+    while end < chars.len()
+      && i64::from(u32::from(chars[end])) - i64::from(u32::from(chars[end - 1])) == delta
+    {
+      end += 1;
+    }
+
+    if delta.abs() != 1 || end - start < 3 {
+      start += 1;
+      continue;
+    }
+
+    let first = chars[start];
+    let base: f64 = if matches!(first, 'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9') {
+      4.0
+    } else if first.is_ascii_digit() {
+      10.0
+    } else {
+      26.0
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let mut guesses = base.log10() + ((end - start) as f64).log10();
+    if delta < 0 {
+      guesses += 2_f64.log10();
+    }
+
+    res.push(Match {
+      pattern: Pattern::Sequence {
+        ascending: delta > 0,
+      },
+      token: chars[start..end].iter().collect(),
+      start,
+      end,
+      guesses_log10: guesses,
+    });
+    start = end;
+  }
+
+  res
+}
+
+/// A year, month and day from three numbers in some order, the year closest to now if there is
+/// more than one reading.
+fn date_from(parts: [(u32, usize); 3], now: i32) -> Option<(i32, u32, u32)> {
+  let year = |(value, len): (u32, usize)| -> Option<i32> {
+    let value = i32::try_from(value).ok()?;
+    match len {
+      4 if (1900..=2099).contains(&value) => Some(value),
+      2 if value >= 50 => Some(1900 + value),
+      2 => Some(2000 + value),
+      _ => None,
+    }
+  };
+  let day_month = |(day, day_len): (u32, usize), (month, month_len): (u32, usize)| {
+    (day_len <= 2 && month_len <= 2 && (1..=31).contains(&day) && (1..=12).contains(&month))
+      .then_some((month, day))
+  };
+
+  let [a, b, c] = parts;
+  let readings = [
+    year(a).zip(day_month(c, b)),
+    year(a).zip(day_month(b, c)),
+    year(c).zip(day_month(a, b)),
+    year(c).zip(day_month(b, a)),
+  ];
+
+  readings
+    .into_iter()
+    .flatten()
+    .min_by_key(|(year, _)| (year - now).abs())
+    .map(|(year, (month, day))| (year, month, day))
+}
+
+/// Dates like `24121999`, `24.12.99` or `1999-12-24`, and years on their own.
+fn dates(chars: &[char], now: i32) -> Vec<Match> {
+  let mut res = Vec::new();
+  let year_space = |year: i32| f64::from((year - now).abs().max(20));
+
+  for start in 0..chars.len() {
+    for end in start + 4..=chars.len().min(start + 10) {
+      let token = &chars[start..end];
+      let text = token.iter().collect::<String>();
+      let digits = token.iter().all(char::is_ascii_digit);
+
+      if digits && token.len() == 4 {
+        if let Some(year) = text.parse().ok().filter(|el| (1900..=2099).contains(el)) {
+          res.push(Match {
+            pattern: Pattern::Year { year },
+            token: text.clone(),
+            start,
+            end,
+            guesses_log10: year_space(year).log10(),
+          });
+        }
+      }
+
+      let (date, separator) = if digits && token.len() <= 8 {
+        let date = (1..=4)
+          .flat_map(|a| (1..=2).map(move |b| (a, b)))
+          .filter(|(a, b)| a + b < token.len() && token.len() - a - b <= 4)
+          .filter_map(|(a, b)| {
+            let part = |from: usize, to: usize| Some((text[from..to].parse().ok()?, to - from));
+            date_from(
+              [part(0, a)?, part(a, a + b)?, part(a + b, text.len())?],
+              now,
+            )
+          })
+          .min_by_key(|(year, _, _)| (year - now).abs());
+        (date, None)
+      } else {
+        let Some(separator) = token
+          .iter()
+          .copied()
+          .find(|el| !el.is_ascii_digit())
+          .filter(|el| " -./\\_".contains(*el))
+        else {
+          continue;
+        };
+        let parts = text
+          .split(separator)
+          .map(|el| {
+            let valid = !el.is_empty() && el.len() <= 4 && el.bytes().all(|el| el.is_ascii_digit());
+            valid.then(|| Some((el.parse().ok()?, el.len())))?
+          })
+          .collect::<Option<Vec<_>>>();
+        let date = parts
+          .and_then(|el| <[(u32, usize); 3]>::try_from(el).ok())
+          .and_then(|el| date_from(el, now));
+        (date, Some(separator))
+      };
+
+      let Some((year, month, day)) = date else {
+        continue;
+      };
+      let mut guesses = (365.0 * year_space(year)).log10();
+      if separator.is_some() {
+        guesses += 4_f64.log10();
+      }
+
+      res.push(Match {
+        pattern: Pattern::Date {
+          year,
+          month,
+          day,
+          separator,
+        },
+        token: text,
+        start,
+        end,
+        guesses_log10: guesses,
+      });
+    }
+  }
+
+  res
+}
+
+/// Picks the matches that together take the fewest guesses, guessing whatever they don't cover
+/// character by character.
+fn cheapest_cover(chars: &[char], matches: Vec<Match>) -> Vec<Match> {
+  // Even a match that looks trivial takes some guesses, zxcvbn uses the same floor
+  let min_guesses = 50_f64.log10();
+  let per_char = cardinality(chars).log10();
+  let mut by_end = vec![Vec::new(); chars.len() + 1];
+  for (idx, el) in matches.iter().enumerate() {
+    by_end[el.end].push(idx);
+  }
+
+  let mut best = vec![0.0; chars.len() + 1];
+  let mut via = vec![None; chars.len() + 1];
+  for end in 1..=chars.len() {
+    best[end] = best[end - 1] + per_char;
+    for &idx in &by_end[end] {
+      let el = &matches[idx];
+      let guesses = best[el.start] + el.guesses_log10.max(min_guesses);
+      if guesses < best[end] {
+        best[end] = guesses;
+        via[end] = Some(idx);
+      }
+    }
+  }
+
+  let mut matches = matches.into_iter().map(Some).collect::<Vec<_>>();
+  let mut res = Vec::new();
+  let mut end = chars.len();
+  while end > 0 {
+    if let Some(mut el) = via[end].and_then(|idx| matches[idx].take()) {
+      el.guesses_log10 = el.guesses_log10.max(min_guesses);
+      end = el.start;
+      res.push(el);
+      continue;
+    }
+
+    let mut start = end - 1;
+    while start > 0 && via[start].is_none() {
+      start -= 1;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let guesses = per_char * (end - start) as f64;
+    res.push(Match {
+      pattern: Pattern::Bruteforce,
+      token: chars[start..end].iter().collect(),
+      start,
+      end,
+      guesses_log10: guesses,
+    });
+    end = start;
+  }
+  res.reverse();
+
+  res
+}
+
+fn display_time(seconds: f64) -> String {
+  const UNITS: [(&str, f64); 6] = [
+    ("second", 1.0),
+    ("minute", 60.0),
+    ("hour", 3600.0),
+    ("day", 86_400.0),
+    ("month", 2_629_800.0),
+    ("year", 31_557_600.0),
+  ];
+
+  if seconds < 1.0 {
+    return "less than a second".to_string();
+  }
+  if seconds >= 100.0 * 31_557_600.0 {
+    return "centuries".to_string();
+  }
+
+  let (unit, size) = UNITS
+    .iter()
+    .rev()
+    .find(|(_, size)| seconds >= *size)
+    .copied()
+    .unwrap_or(UNITS[0]);
+  #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+  let count = (seconds / size).round() as u64;
+  if count == 1 {
+    format!("1 {unit}")
+  } else {
+    format!("{count} {unit}s")
+  }
+}
+
+fn suggestions(chars: &[char], patterns: &[Match], score: u8, breached: u64) -> Vec<String> {
+  let mut res = Vec::<&str>::new();
+  let mut add = |el| {
+    if !res.contains(&el) {
+      res.push(el);
+    }
+  };
+
+  if breached > 0 {
+    add("This password has been seen in data breaches, don't use it anywhere");
+  }
+
+  for el in patterns {
+    match &el.pattern {
+      Pattern::Dictionary {
+        l33t,
+        reversed,
+        user_input,
+        ..
+      } => {
+        if *user_input {
+          add("Don't use your name or other details about you");
+        } else {
+          add("Avoid common passwords");
+        }
+        if !l33t.is_empty() {
+          add("Predictable substitutions like '@' for 'a' don't help much");
+        }
+        if *reversed {
+          add("Words spelled backwards aren't much harder to guess");
+        }
+        let token = el.token.chars().collect::<Vec<_>>();
+        if token.len() > 1
+          && uppercase_variations(&token) <= 2.0
+          && token.iter().any(|c| c.is_uppercase())
+        {
+          add("Capitalizing the first letter or all of them doesn't help much");
+        }
+      }
+      Pattern::Spatial { .. } => add("Avoid keyboard patterns like qwerty or asdf"),
+      Pattern::Repeat { .. } => add("Avoid repeated characters and words"),
+      Pattern::Sequence { .. } => add("Avoid sequences like abc or 6543"),
+      Pattern::Date { .. } | Pattern::Year { .. } => {
+        add("Avoid dates and years that are associated with you");
+      }
+      Pattern::Bruteforce => {}
+    }
+  }
+
+  if score < 3 {
+    if chars.len() < 12 {
+      add("Use at least 12 characters");
+    }
+    add("Add another word or two, uncommon words are better");
+    let classes = [
+      chars.iter().any(|el| el.is_lowercase()),
+      chars.iter().any(|el| el.is_uppercase()),
+      chars.iter().any(char::is_ascii_digit),
+      chars.iter().any(|el| !el.is_alphanumeric()),
+    ];
+    if classes.iter().filter(|el| **el).count() < 2 {
+      add("Mix in uppercase letters, digits or symbols");
+    }
+  }
+
+  res.into_iter().map(str::to_string).collect()
+}
@@ -2,14 +2,20 @@ use std::collections::BTreeMap;
 
 use super::day_13::{insert, MyState};
 use axum::{
-  extract::{Path, State},
+  extract::{Path, Query, State},
   response::IntoResponse,
   routing::{get, post},
   Json, Router,
 };
+use isocountry::CountryCode;
+use s2::{cell::Cell, cellid::CellID};
 use sqlx::PgPool;
 
-use super::AppError;
+use super::{AppError, BadRequest};
+use crate::{
+  feed::{self, Change},
+  pagination::{Page, Pagination},
+};
 
 pub fn get_routes(pool: PgPool) -> Router {
   let state = MyState { pool };
@@ -47,7 +53,12 @@ async fn reset(State(state): State<MyState>) -> Result<(), AppError> {
     r"
     CREATE TABLE regions (
       id INT PRIMARY KEY,
-      name VARCHAR(50)
+      name VARCHAR(50),
+      parent_id INT,
+      country_code CHAR(2),
+      latitude DOUBLE PRECISION,
+      longitude DOUBLE PRECISION,
+      s2_cell VARCHAR(16)
     )"
   )
   .execute(&state.pool)
@@ -56,10 +67,86 @@ async fn reset(State(state): State<MyState>) -> Result<(), AppError> {
   Ok(())
 }
 
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
+pub(crate) struct Region {
+  pub(crate) id: i32,
+  pub(crate) name: String,
+  pub(crate) parent_id: Option<i32>,
+  pub(crate) country_code: Option<String>,
+  pub(crate) latitude: Option<f64>,
+  pub(crate) longitude: Option<f64>,
+  pub(crate) s2_cell: Option<String>,
+}
+
+impl Region {
+  /// Validates the hierarchy and geo fields, normalizing the country code to ISO alpha-2 and
+  /// filling in a missing centroid from the S2 cell.
+  pub(crate) fn normalize(mut self) -> Result<Self, String> {
+    if self.parent_id == Some(self.id) {
+      return Err(format!("Region {} cannot be its own parent", self.id));
+    }
+
+    if let Some(code) = &self.country_code {
+      let code = code.trim();
+      let country = if code.len() == 3 {
+        CountryCode::for_alpha3_caseless(code)
+      } else {
+        CountryCode::for_alpha2_caseless(code)
+      }
+      .map_err(|_| format!("Unknown country code '{code}'"))?;
+
+      self.country_code = Some(country.alpha2().to_string());
+    }
+
+    if let Some(token) = &self.s2_cell {
+      let token = token.trim().to_ascii_lowercase();
+      let cell = parse_s2_token(&token)?;
+
+      if self.latitude.is_none() && self.longitude.is_none() {
+        let center = Cell::from(cell).center();
+        self.latitude = Some(center.latitude().deg());
+        self.longitude = Some(center.longitude().deg());
+      }
+
+      self.s2_cell = Some(token);
+    }
+
+    match (self.latitude, self.longitude) {
+      (Some(lat), Some(lon)) => {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+          return Err(format!("Centroid ({lat}, {lon}) is out of range"));
+        }
+      }
+      (None, None) => {}
+      _ => return Err("Latitude and longitude must be given together".to_string()),
+    }
+
+    Ok(self)
+  }
+}
+
+/// Parses an S2 cell token, the hex form of the cell id with trailing zeros removed.
+fn parse_s2_token(token: &str) -> Result<CellID, String> {
+  let invalid = || format!("Invalid S2 cell token '{token}'");
+
+  if token.is_empty() || token.len() > 16 {
+    return Err(invalid());
+  }
+
+  let id = u64::from_str_radix(token, 16).map_err(|_| invalid())? << (4 * (16 - token.len()));
+  let cell = CellID(id);
+
+  if cell.is_valid() {
+    Ok(cell)
+  } else {
+    Err(invalid())
+  }
+}
+
 #[derive(serde::Deserialize, Debug)]
-struct Region {
-  id: i32,
-  name: String,
+struct TotalQuery {
+  #[serde(default)]
+  rollup: bool,
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -84,20 +171,46 @@ async fn insert_region(
   State(state): State<MyState>,
   Json(payload): Json<Vec<Region>>,
 ) -> Result<impl IntoResponse, AppError> {
+  let payload = payload
+    .into_iter()
+    .map(Region::normalize)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(BadRequest)?;
+
+  let mut tx = state.pool.begin().await?;
+
   for el in payload {
     let _ = sqlx::query!(
-      "INSERT INTO regions (id, name) VALUES ($1, $2)",
+      r"INSERT INTO regions (id, name, parent_id, country_code, latitude, longitude, s2_cell)
+      VALUES ($1, $2, $3, $4, $5, $6, $7)",
       el.id,
       el.name,
+      el.parent_id,
+      el.country_code,
+      el.latitude,
+      el.longitude,
+      el.s2_cell,
     )
-    .execute(&state.pool)
+    .execute(&mut *tx)
     .await?;
+
+    feed::publish(&mut *tx, &Change::RegionInserted(el)).await?;
   }
 
+  tx.commit().await?;
+
   Ok(())
 }
 
-async fn total(State(state): State<MyState>) -> Result<Json<Vec<RegionTotal>>, AppError> {
+async fn total(
+  Query(query): Query<TotalQuery>,
+  pagination: Pagination,
+  State(state): State<MyState>,
+) -> Result<Page<RegionTotal>, AppError> {
+  if query.rollup {
+    return Ok(pagination.page(rollup_total(&state).await?));
+  }
+
   let res = sqlx::query_as!(
     RegionTotal,
     r#"SELECT 
@@ -114,17 +227,50 @@ async fn total(State(state): State<MyState>) -> Result<Json<Vec<RegionTotal>>, A
       "#
   )
   .fetch_all(&state.pool)
-  .await?
-  .into_iter()
-  .collect::<Vec<_>>();
+  .await?;
+
+  Ok(pagination.page(res))
+}
+
+/// Like [`total`], but every region also counts the orders of all regions below it.
+async fn rollup_total(state: &MyState) -> Result<Vec<RegionTotal>, AppError> {
+  // `path` keeps a bad parent_id cycle from recursing forever.
+  let res = sqlx::query_as!(
+    RegionTotal,
+    r#"WITH RECURSIVE ancestry AS (
+        SELECT id AS region_id, id AS ancestor_id, parent_id, ARRAY[id] AS path
+        FROM regions
+        UNION ALL
+        SELECT a.region_id, r.id, r.parent_id, a.path || r.id
+        FROM ancestry a
+          JOIN regions r ON r.id = a.parent_id
+        WHERE r.id <> ALL(a.path)
+      )
+      SELECT
+        name as "region!",
+        SUM(quantity)::INT as "total!"
+      FROM
+        ancestry
+        JOIN orders ON orders.region_id = ancestry.region_id
+        JOIN regions ON regions.id = ancestry.ancestor_id
+      GROUP BY
+        ancestor_id,
+        name
+      ORDER BY
+        name;
+      "#
+  )
+  .fetch_all(&state.pool)
+  .await?;
 
-  Ok(Json(res))
+  Ok(res)
 }
 
 async fn best(
   Path(number): Path<usize>,
+  pagination: Pagination,
   State(state): State<MyState>,
-) -> Result<Json<Vec<RegionBestResp>>, AppError> {
+) -> Result<Page<RegionBestResp>, AppError> {
   let res = sqlx::query_as!(
     RegionBest,
     r#"SELECT
@@ -170,5 +316,5 @@ async fn best(
     })
     .collect::<Vec<_>>();
 
-  Ok(Json(res))
+  Ok(pagination.page(res))
 }
@@ -0,0 +1,121 @@
+//! Who may join and who may post in a room.
+//!
+//! Rooms without an ACL are public. Admins can join and post anywhere. Users connecting without a
+//! token, when that is allowed at all, are never members of anything.
+
+use std::{
+  collections::{BTreeSet, HashMap},
+  sync::RwLock,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::room::RoomId;
+use crate::auth::Claims;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclMode {
+  #[default]
+  Public,
+  /// Only members can join.
+  InviteOnly,
+  /// Anyone can join, only members can post.
+  ReadOnly,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoomAcl {
+  #[serde(default)]
+  pub mode: AclMode,
+  #[serde(default)]
+  pub members: BTreeSet<String>,
+}
+
+/// The user behind a connection.
+#[derive(Debug, Clone)]
+pub struct Identity {
+  pub user: String,
+  pub admin: bool,
+  /// Took the user from the path instead of a token.
+  pub anonymous: bool,
+}
+
+impl From<Claims> for Identity {
+  fn from(claims: Claims) -> Self {
+    Self {
+      user: claims.sub,
+      admin: claims.admin,
+      anonymous: false,
+    }
+  }
+}
+
+impl Identity {
+  fn is_member(&self, acl: &RoomAcl) -> bool {
+    self.admin || (!self.anonymous && acl.members.contains(&self.user))
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Acls {
+  rooms: RwLock<HashMap<RoomId, RoomAcl>>,
+}
+
+impl Acls {
+  /// Reads the ACL of every room that isn't public as a JSON object keyed by room id from
+  /// `CHAT_ROOM_ACLS`. ACLs kept by the bus are loaded over these.
+  pub fn from_env() -> Self {
+    let rooms = std::env::var("CHAT_ROOM_ACLS")
+      .ok()
+      .and_then(|el| {
+        serde_json::from_str::<HashMap<RoomId, RoomAcl>>(&el)
+          .map_err(|e| warn!("Ignoring invalid CHAT_ROOM_ACLS: {:?}", e))
+          .ok()
+      })
+      .unwrap_or_default();
+
+    Self {
+      rooms: RwLock::new(rooms),
+    }
+  }
+
+  /// Replaces the ACLs of the rooms given, leaving the others alone.
+  pub fn load(&self, rooms: HashMap<RoomId, RoomAcl>) {
+    for (room, acl) in rooms {
+      self.set(room, acl);
+    }
+  }
+
+  pub fn get(&self, room: RoomId) -> RoomAcl {
+    self
+      .rooms
+      .read()
+      .unwrap()
+      .get(&room)
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  pub fn set(&self, room: RoomId, acl: RoomAcl) {
+    let mut rooms = self.rooms.write().unwrap();
+    if acl == RoomAcl::default() {
+      let _ = rooms.remove(&room);
+    } else {
+      let _ = rooms.insert(room, acl);
+    }
+  }
+
+  pub fn can_join(&self, room: RoomId, identity: &Identity) -> bool {
+    let acl = self.get(room);
+
+    acl.mode != AclMode::InviteOnly || identity.is_member(&acl)
+  }
+
+  pub fn can_post(&self, room: RoomId, identity: &Identity) -> bool {
+    let acl = self.get(room);
+
+    acl.mode == AclMode::Public || identity.is_member(&acl)
+  }
+}
@@ -0,0 +1,377 @@
+//! Fans room events out to every server instance.
+//!
+//! Connections never touch [`super::room::RoomState`] directly when something happens in a room,
+//! they publish an event on the bus. Every instance, including the one that published it, applies
+//! the event to its own copy of the room once the bus delivers it.
+
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use axum::async_trait;
+use sqlx::{postgres::PgListener, types::Json, PgExecutor, PgPool};
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use tracing::warn;
+
+use super::{acl::RoomAcl, protocol::Tweet, room::RoomId, Seq};
+
+/// The Postgres channel room events are published on.
+const CHANNEL: &str = "chat";
+
+/// How often [`PgBus`] adds up the views counted since the last flush in the database.
+const VIEWS_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct BusEvent {
+  pub room: RoomId,
+  #[serde(flatten)]
+  pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+  Tweet(Tweet),
+  Typing {
+    user: String,
+  },
+  /// A connection opened, users can have several.
+  Join {
+    user: String,
+  },
+  /// A connection closed.
+  Leave {
+    user: String,
+  },
+  Closed,
+  /// The room's ACL changed.
+  Acl(RoomAcl),
+}
+
+#[async_trait]
+pub trait RoomBus: Send + Sync + std::fmt::Debug {
+  /// Numbers the tweet and publishes it, returning its sequence id. Tweets of one room are
+  /// delivered in sequence order.
+  async fn publish_tweet(
+    &self,
+    room: RoomId,
+    user: String,
+    message: String,
+  ) -> Result<Seq, anyhow::Error>;
+
+  async fn publish(&self, event: BusEvent) -> Result<(), anyhow::Error>;
+
+  /// Publishes the room's new ACL and keeps it for instances started later.
+  async fn publish_acl(&self, room: RoomId, acl: RoomAcl) -> Result<(), anyhow::Error> {
+    self
+      .publish(BusEvent {
+        room,
+        kind: EventKind::Acl(acl),
+      })
+      .await
+  }
+
+  /// The ACLs kept so far, public ones included.
+  async fn acls(&self) -> Result<HashMap<RoomId, RoomAcl>, anyhow::Error> {
+    Ok(HashMap::new())
+  }
+
+  /// Events published from now on, on any instance.
+  fn subscribe(&self) -> Receiver<BusEvent>;
+
+  /// Drops whatever the bus keeps for a room that was garbage-collected.
+  fn forget(&self, _room: RoomId) {}
+
+  fn add_view(&self);
+
+  /// Views across every instance.
+  async fn views(&self) -> Result<u64, anyhow::Error>;
+
+  async fn reset_views(&self) -> Result<(), anyhow::Error>;
+}
+
+/// Picks the backend from `CHAT_BUS`, `postgres` shares rooms with every instance using the same
+/// database while anything else keeps them in this process.
+pub fn from_env(pool: PgPool) -> Arc<dyn RoomBus> {
+  match std::env::var("CHAT_BUS").as_deref() {
+    Ok("postgres") => Arc::new(PgBus::new(pool)),
+    _ => Arc::new(MemoryBus::new()),
+  }
+}
+
+/// Keeps rooms in this process only.
+#[derive(Debug)]
+pub struct MemoryBus {
+  sender: Sender<BusEvent>,
+  last_seqs: Mutex<HashMap<RoomId, Seq>>,
+  views: AtomicU64,
+}
+
+impl MemoryBus {
+  pub fn new() -> Self {
+    Self {
+      sender: broadcast::channel(1024).0,
+      last_seqs: Mutex::new(HashMap::new()),
+      views: AtomicU64::new(0),
+    }
+  }
+}
+
+#[async_trait]
+impl RoomBus for MemoryBus {
+  async fn publish_tweet(
+    &self,
+    room: RoomId,
+    user: String,
+    message: String,
+  ) -> Result<Seq, anyhow::Error> {
+    // Held while sending so tweets go out in sequence order
+    let mut last_seqs = self.last_seqs.lock().unwrap();
+    let seq = last_seqs.entry(room).or_default();
+    *seq += 1;
+
+    let tweet = Tweet {
+      seq: *seq,
+      user,
+      message,
+    };
+
+    let _ = self.sender.send(BusEvent {
+      room,
+      kind: EventKind::Tweet(tweet),
+    });
+
+    Ok(*seq)
+  }
+
+  async fn publish(&self, event: BusEvent) -> Result<(), anyhow::Error> {
+    let _ = self.sender.send(event);
+
+    Ok(())
+  }
+
+  fn subscribe(&self) -> Receiver<BusEvent> {
+    self.sender.subscribe()
+  }
+
+  fn forget(&self, room: RoomId) {
+    let _ = self.last_seqs.lock().unwrap().remove(&room);
+  }
+
+  fn add_view(&self) {
+    let _ = self.views.fetch_add(1, Ordering::Relaxed);
+  }
+
+  async fn views(&self) -> Result<u64, anyhow::Error> {
+    Ok(self.views.load(Ordering::Relaxed))
+  }
+
+  async fn reset_views(&self) -> Result<(), anyhow::Error> {
+    self.views.store(0, Ordering::Relaxed);
+
+    Ok(())
+  }
+}
+
+/// Shares rooms between every instance using the same database over `LISTEN/NOTIFY`. Sequence
+/// ids come from `chat_rooms`, ACLs are kept in `chat_room_acls` and views are added up in
+/// `chat_views`.
+#[derive(Debug)]
+pub struct PgBus {
+  pool: PgPool,
+  sender: Sender<BusEvent>,
+  /// Views counted since the last flush to `chat_views`.
+  pending_views: Arc<AtomicU64>,
+}
+
+impl PgBus {
+  pub fn new(pool: PgPool) -> Self {
+    let sender = broadcast::channel(1024).0;
+    let pending_views = Arc::new(AtomicU64::new(0));
+
+    spawn_listener(pool.clone(), sender.clone());
+    spawn_views_flusher(pool.clone(), pending_views.clone());
+
+    Self {
+      pool,
+      sender,
+      pending_views,
+    }
+  }
+}
+
+async fn notify<'e>(executor: impl PgExecutor<'e>, event: &BusEvent) -> Result<(), anyhow::Error> {
+  let payload = serde_json::to_string(event)?;
+
+  let _ = sqlx::query!("SELECT pg_notify($1, $2)", CHANNEL, payload)
+    .execute(executor)
+    .await?;
+
+  Ok(())
+}
+
+#[async_trait]
+impl RoomBus for PgBus {
+  async fn publish_tweet(
+    &self,
+    room: RoomId,
+    user: String,
+    message: String,
+  ) -> Result<Seq, anyhow::Error> {
+    let mut tx = self.pool.begin().await?;
+
+    // The row stays locked until the commit, and notifications are delivered in commit order
+    let seq = sqlx::query_scalar!(
+      "INSERT INTO chat_rooms (id, last_seq) VALUES ($1, 1)
+      ON CONFLICT (id) DO UPDATE SET last_seq = chat_rooms.last_seq + 1
+      RETURNING last_seq",
+      room
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    let seq = Seq::try_from(seq)?;
+
+    let tweet = Tweet { seq, user, message };
+    notify(
+      &mut *tx,
+      &BusEvent {
+        room,
+        kind: EventKind::Tweet(tweet),
+      },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(seq)
+  }
+
+  async fn publish(&self, event: BusEvent) -> Result<(), anyhow::Error> {
+    notify(&self.pool, &event).await
+  }
+
+  async fn publish_acl(&self, room: RoomId, acl: RoomAcl) -> Result<(), anyhow::Error> {
+    let mut tx = self.pool.begin().await?;
+
+    let _ = sqlx::query!(
+      "INSERT INTO chat_room_acls (room_id, acl) VALUES ($1, $2)
+      ON CONFLICT (room_id) DO UPDATE SET acl = EXCLUDED.acl",
+      room,
+      Json(&acl) as _
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    notify(
+      &mut *tx,
+      &BusEvent {
+        room,
+        kind: EventKind::Acl(acl),
+      },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+  }
+
+  async fn acls(&self) -> Result<HashMap<RoomId, RoomAcl>, anyhow::Error> {
+    let rows = sqlx::query!(r#"SELECT room_id, acl as "acl: Json<RoomAcl>" FROM chat_room_acls"#)
+      .fetch_all(&self.pool)
+      .await?;
+
+    Ok(rows.into_iter().map(|el| (el.room_id, el.acl.0)).collect())
+  }
+
+  fn subscribe(&self) -> Receiver<BusEvent> {
+    self.sender.subscribe()
+  }
+
+  fn add_view(&self) {
+    let _ = self.pending_views.fetch_add(1, Ordering::Relaxed);
+  }
+
+  async fn views(&self) -> Result<u64, anyhow::Error> {
+    let views = sqlx::query_scalar!("SELECT views FROM chat_views")
+      .fetch_one(&self.pool)
+      .await?;
+
+    Ok(u64::try_from(views)? + self.pending_views.load(Ordering::Relaxed))
+  }
+
+  async fn reset_views(&self) -> Result<(), anyhow::Error> {
+    self.pending_views.store(0, Ordering::Relaxed);
+
+    let _ = sqlx::query!("UPDATE chat_views SET views = 0")
+      .execute(&self.pool)
+      .await?;
+
+    Ok(())
+  }
+}
+
+fn spawn_listener(pool: PgPool, sender: Sender<BusEvent>) {
+  tokio::spawn(async move {
+    loop {
+      if let Err(e) = listen(&pool, &sender).await {
+        warn!(
+          "Chat bus listener failed, events may have been missed: {:?}",
+          e
+        );
+      }
+
+      tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+  });
+}
+
+async fn listen(pool: &PgPool, sender: &Sender<BusEvent>) -> Result<(), anyhow::Error> {
+  let mut listener = PgListener::connect_with(pool).await?;
+  listener.listen(CHANNEL).await?;
+
+  loop {
+    let notification = listener.recv().await?;
+
+    match serde_json::from_str::<BusEvent>(notification.payload()) {
+      Ok(event) => drop(sender.send(event)),
+      Err(e) => warn!(
+        "Failed to parse chat event {:?}: {:?}",
+        notification.payload(),
+        e
+      ),
+    }
+  }
+}
+
+fn spawn_views_flusher(pool: PgPool, pending_views: Arc<AtomicU64>) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(VIEWS_FLUSH_INTERVAL);
+
+    loop {
+      let _ = interval.tick().await;
+
+      let views = pending_views.swap(0, Ordering::Relaxed);
+      if views == 0 {
+        continue;
+      }
+
+      let res = sqlx::query!(
+        "UPDATE chat_views SET views = views + $1",
+        i64::try_from(views).unwrap_or(i64::MAX)
+      )
+      .execute(&pool)
+      .await;
+
+      if let Err(e) = res {
+        warn!("Failed to flush chat views: {:?}", e);
+        let _ = pending_views.fetch_add(views, Ordering::Relaxed);
+      }
+    }
+  });
+}
@@ -0,0 +1,340 @@
+//! Keeping WebSocket connections healthy: heartbeats, idle timeouts, a bounded outbound queue per
+//! connection and limits on how many connections a room or an IP can have.
+
+use std::{
+  borrow::Cow,
+  collections::{HashMap, VecDeque},
+  net::IpAddr,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures_util::{sink::SinkExt, stream::SplitSink};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use super::{protocol::ServerFrame, room::RoomId};
+
+/// The longest ping interval or idle timeout, a day.
+const MAX_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionConfig {
+  /// How often the server pings every connection.
+  pub ping_interval_secs: u64,
+  /// Connections the client sent nothing on, not even a pong, for this long are closed.
+  pub idle_timeout_secs: u64,
+  /// Frames waiting to be written to a single connection.
+  pub outbound_queue: usize,
+  pub slow_consumer: SlowConsumerPolicy,
+  /// Connections to a room on this instance.
+  pub max_connections_per_room: usize,
+  /// Connections from one IP on this instance.
+  pub max_connections_per_ip: usize,
+  /// Lets clients without a token connect as the user in the path, to public rooms only.
+  pub allow_anonymous: bool,
+  /// Takes the client's IP from `X-Forwarded-For`. Only for running behind a proxy that sets
+  /// it, anyone else can send whatever they like in it.
+  pub trust_forwarded_for: bool,
+}
+
+impl Default for ConnectionConfig {
+  fn default() -> Self {
+    Self {
+      ping_interval_secs: 30,
+      idle_timeout_secs: 120,
+      outbound_queue: 256,
+      slow_consumer: SlowConsumerPolicy::DropOldest,
+      max_connections_per_room: 1000,
+      max_connections_per_ip: 50,
+      allow_anonymous: false,
+      trust_forwarded_for: false,
+    }
+  }
+}
+
+impl ConnectionConfig {
+  /// Reads the config as JSON from `CHAT_CONNECTIONS`, any field left out keeps its default.
+  pub fn from_env() -> Self {
+    let Ok(config) = std::env::var("CHAT_CONNECTIONS") else {
+      return Self::default();
+    };
+
+    serde_json::from_str::<Self>(&config)
+      .map_err(|e| e.to_string())
+      .and_then(Self::validate)
+      .unwrap_or_else(|e| {
+        warn!("Ignoring invalid CHAT_CONNECTIONS: {:?}", e);
+        Self::default()
+      })
+  }
+
+  /// A heartbeat can't tick every 0 seconds, connections have to be pinged before they count as
+  /// idle, and deadlines have to stay within what an `Instant` can hold.
+  fn validate(self) -> Result<Self, String> {
+    if self.ping_interval_secs == 0 {
+      return Err("ping_interval_secs has to be at least 1".to_string());
+    }
+    if !(self.ping_interval_secs.saturating_add(1)..=MAX_INTERVAL_SECS)
+      .contains(&self.idle_timeout_secs)
+    {
+      return Err(format!(
+        "idle_timeout_secs has to be more than ping_interval_secs and at most {MAX_INTERVAL_SECS}"
+      ));
+    }
+
+    Ok(self)
+  }
+
+  pub const fn ping_interval(&self) -> Duration {
+    Duration::from_secs(self.ping_interval_secs)
+  }
+
+  pub const fn idle_timeout(&self) -> Duration {
+    Duration::from_secs(self.idle_timeout_secs)
+  }
+}
+
+/// What to do when a client reads slower than frames come in and its queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlowConsumerPolicy {
+  /// Make room by dropping the oldest frame, the client is told how many tweets it lost.
+  DropOldest,
+  Disconnect,
+}
+
+/// Why the server closed a connection, sent in the close frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+  RoomClosed,
+  /// The client went away, there is nobody left to tell.
+  ClientGone,
+  Idle,
+  SlowConsumer,
+  ServerError,
+}
+
+impl std::fmt::Display for CloseReason {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.reason())
+  }
+}
+
+impl CloseReason {
+  const fn code(self) -> u16 {
+    match self {
+      Self::RoomClosed | Self::ClientGone => 1000,
+      Self::Idle => 1001,
+      Self::SlowConsumer => 1008,
+      Self::ServerError => 1011,
+    }
+  }
+
+  const fn reason(self) -> &'static str {
+    match self {
+      Self::RoomClosed => "Room was closed",
+      Self::ClientGone => "Bye",
+      Self::Idle => "Idle timeout",
+      Self::SlowConsumer => "Too slow to keep up",
+      Self::ServerError => "Server error",
+    }
+  }
+
+  pub fn frame(self) -> Message {
+    Message::Close(Some(CloseFrame {
+      code: self.code(),
+      reason: Cow::Borrowed(self.reason()),
+    }))
+  }
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+  frames: VecDeque<ServerFrame>,
+  /// Tweets dropped to make room since the client was last told.
+  dropped: u64,
+  closed: Option<CloseReason>,
+}
+
+/// Frames waiting to be written to one connection.
+#[derive(Debug)]
+pub struct OutboundQueue {
+  state: Mutex<QueueState>,
+  notify: Notify,
+  capacity: usize,
+  policy: SlowConsumerPolicy,
+}
+
+impl OutboundQueue {
+  pub fn new(capacity: usize, policy: SlowConsumerPolicy) -> Self {
+    Self {
+      state: Mutex::new(QueueState::default()),
+      notify: Notify::new(),
+      capacity: capacity.max(1),
+      policy,
+    }
+  }
+
+  /// Queues the frame, applying the slow consumer policy when the queue is full. Returns false
+  /// once the queue is closed.
+  pub fn push(&self, frame: ServerFrame) -> bool {
+    let mut state = self.state.lock().unwrap();
+    if state.closed.is_some() {
+      return false;
+    }
+
+    if state.frames.len() >= self.capacity {
+      match self.policy {
+        SlowConsumerPolicy::DropOldest => {
+          if let Some(ServerFrame::Message(_)) = state.frames.pop_front() {
+            state.dropped += 1;
+          }
+        }
+        SlowConsumerPolicy::Disconnect => {
+          state.frames.clear();
+          state.closed = Some(CloseReason::SlowConsumer);
+          self.notify.notify_one();
+
+          return false;
+        }
+      }
+    }
+
+    state.frames.push_back(frame);
+    self.notify.notify_one();
+
+    true
+  }
+
+  /// Stops taking frames. Whatever is queued is still written before the close frame.
+  pub fn close(&self, reason: CloseReason) {
+    let mut state = self.state.lock().unwrap();
+    let _ = state.closed.get_or_insert(reason);
+    self.notify.notify_one();
+  }
+
+  /// Takes everything queued, with a [`ServerFrame::Lost`] first if tweets were dropped.
+  fn drain(&self) -> (Vec<ServerFrame>, Option<CloseReason>) {
+    let mut state = self.state.lock().unwrap();
+
+    let mut frames = Vec::with_capacity(state.frames.len() + 1);
+    if state.dropped > 0 {
+      frames.push(ServerFrame::Lost {
+        count: state.dropped,
+      });
+      state.dropped = 0;
+    }
+    frames.extend(state.frames.drain(..));
+
+    (frames, state.closed)
+  }
+}
+
+/// Writes queued frames to the socket and pings it, until the queue is closed or the client is
+/// gone. `render` turns a frame into text, or `None` if the client has no use for it.
+pub async fn write(
+  mut sink: SplitSink<WebSocket, Message>,
+  queue: Arc<OutboundQueue>,
+  ping_interval: Duration,
+  mut render: impl FnMut(&ServerFrame) -> Option<String> + Send,
+) {
+  let mut ping =
+    tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+
+  loop {
+    tokio::select! {
+      () = queue.notify.notified() => {
+        let (frames, closed) = queue.drain();
+
+        for frame in frames {
+          let Some(text) = render(&frame) else {
+            continue;
+          };
+
+          if sink.send(Message::Text(text)).await.is_err() {
+            return;
+          }
+        }
+
+        if let Some(reason) = closed {
+          let _ = sink.send(reason.frame()).await;
+          return;
+        }
+      }
+      _ = ping.tick() => {
+        if sink.send(Message::Ping(Vec::new())).await.is_err() {
+          return;
+        }
+      }
+    }
+  }
+}
+
+/// Open connections per room and per IP on this instance.
+#[derive(Debug, Default)]
+pub struct ConnectionLimits {
+  counts: Mutex<(HashMap<RoomId, usize>, HashMap<IpAddr, usize>)>,
+}
+
+/// Counts a connection until dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+  limits: Arc<ConnectionLimits>,
+  room: RoomId,
+  ip: Option<IpAddr>,
+}
+
+impl ConnectionLimits {
+  /// Counts a new connection, or returns `None` if the room or IP already has too many.
+  pub fn acquire(
+    self: &Arc<Self>,
+    config: &ConnectionConfig,
+    room: RoomId,
+    ip: Option<IpAddr>,
+  ) -> Option<ConnectionPermit> {
+    let mut counts = self.counts.lock().unwrap();
+    let (rooms, ips) = &mut *counts;
+
+    if rooms.get(&room).copied().unwrap_or_default() >= config.max_connections_per_room {
+      return None;
+    }
+    if let Some(ip) = ip {
+      if ips.get(&ip).copied().unwrap_or_default() >= config.max_connections_per_ip {
+        return None;
+      }
+      *ips.entry(ip).or_default() += 1;
+    }
+    *rooms.entry(room).or_default() += 1;
+
+    Some(ConnectionPermit {
+      limits: self.clone(),
+      room,
+      ip,
+    })
+  }
+}
+
+impl Drop for ConnectionPermit {
+  fn drop(&mut self) {
+    let mut counts = self.limits.counts.lock().unwrap();
+    let (rooms, ips) = &mut *counts;
+
+    release(rooms, self.room);
+    if let Some(ip) = self.ip {
+      release(ips, ip);
+    }
+  }
+}
+
+fn release<K: std::hash::Hash + Eq>(counts: &mut HashMap<K, usize>, key: K) {
+  if let Some(count) = counts.get_mut(&key) {
+    *count -= 1;
+    if *count == 0 {
+      let _ = counts.remove(&key);
+    }
+  }
+}
@@ -0,0 +1,731 @@
+#![allow(clippy::unwrap_used, clippy::significant_drop_tightening)]
+
+use axum::{
+  extract::{
+    ws::{Message, Message::Text, WebSocket},
+    ConnectInfo, Path, Query, State, WebSocketUpgrade,
+  },
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{delete, get, post},
+  Json, Router,
+};
+use futures_util::stream::StreamExt;
+use sqlx::PgPool;
+use std::{
+  collections::{BTreeMap, HashMap},
+  net::{IpAddr, SocketAddr},
+  sync::{Arc, RwLock},
+  time::Duration,
+};
+use tokio::{
+  sync::broadcast::{error::RecvError, Receiver},
+  time::Instant,
+};
+use tracing::{info, warn};
+
+use super::{AppError, BadRequest};
+use crate::auth::{Auth, AuthError};
+use acl::{Acls, Identity, RoomAcl};
+use bus::{BusEvent, EventKind, RoomBus};
+use connection::{
+  CloseReason, ConnectionConfig, ConnectionLimits, ConnectionPermit, OutboundQueue,
+};
+use moderation::{ModerationConfig, ModerationEvent, Moderator, Verdict};
+use protocol::{ClientFrame, ErrorCode, ServerFrame, Tweet};
+use room::{RoomId, RoomState, RoomStats, RoomSummary};
+
+mod acl;
+mod bus;
+mod connection;
+mod moderation;
+mod protocol;
+mod room;
+
+/// Fails when the room ACLs kept by the bus can't be loaded, rather than serving rooms as public.
+pub async fn get_routes(pool: PgPool, auth: Arc<Auth>) -> Result<Router, anyhow::Error> {
+  let state = BirdAppState::new(
+    auth,
+    bus::from_env(pool),
+    Moderator::from_env(),
+    ConnectionConfig::from_env(),
+  );
+  // Subscribed before loading, so ACL changes made meanwhile are applied after what was loaded
+  let events = state.bus.subscribe();
+  state.acls.load(state.bus.acls().await?);
+  spawn_dispatcher(state.clone(), events);
+  spawn_room_gc(state.clone());
+
+  Ok(
+    Router::new()
+      .route("/19/ws/ping", get(ping))
+      .route("/19/reset", post(reset))
+      .route("/19/views", get(views))
+      .route("/19/ws/room/:room_id", get(tweet))
+      .route("/19/ws/room/:room_id/user/:user", get(tweet))
+      .route("/19/protocol/schema.json", get(schema))
+      .route("/19/rooms", get(list_rooms))
+      .route("/19/rooms/:room_id", delete(close_room))
+      .route("/19/rooms/:room_id/presence", get(presence))
+      .route("/19/rooms/:room_id/stats", get(room_stats))
+      .route("/19/rooms/:room_id/acl", get(room_acl).put(set_room_acl))
+      .route(
+        "/19/admin/moderation/rules",
+        get(moderation_rules).put(set_moderation_rules),
+      )
+      .route("/19/admin/moderation/events", get(moderation_events))
+      .with_state(state),
+  )
+}
+
+async fn ping(ws: WebSocketUpgrade, State(state): State<BirdAppState>) -> Response {
+  ws.on_upgrade(move |socket| handle_ping(socket, state.connections))
+}
+
+async fn handle_ping(mut socket: WebSocket, config: Arc<ConnectionConfig>) {
+  let mut started = false;
+  let mut heartbeat = tokio::time::interval_at(
+    Instant::now() + config.ping_interval(),
+    config.ping_interval(),
+  );
+  let mut idle_deadline = Instant::now() + config.idle_timeout();
+
+  loop {
+    let msg = tokio::select! {
+      msg = socket.recv() => msg,
+      _ = heartbeat.tick() => {
+        if socket.send(Message::Ping(Vec::new())).await.is_err() {
+          return;
+        }
+        continue;
+      }
+      () = tokio::time::sleep_until(idle_deadline) => {
+        let _ = socket.send(CloseReason::Idle.frame()).await;
+        return;
+      }
+    };
+
+    let Some(Ok(msg)) = msg else {
+      return;
+    };
+    idle_deadline = Instant::now() + config.idle_timeout();
+
+    let Text(msg) = &msg else {
+      continue;
+    };
+
+    if msg == "serve" {
+      started = true;
+    } else if msg == "ping" && started && socket.send(Text("pong".to_string())).await.is_err() {
+      return;
+    }
+  }
+}
+
+async fn reset(State(state): State<BirdAppState>) -> Result<(), AppError> {
+  state.bus.reset_views().await?;
+  for room in state.rooms.read().unwrap().values() {
+    room.reset_views();
+  }
+
+  Ok(())
+}
+
+async fn views(State(state): State<BirdAppState>) -> Result<String, AppError> {
+  Ok(state.bus.views().await?.to_string())
+}
+
+type Seq = u64;
+
+/// How often rooms are checked for having been empty longer than [`room::EMPTY_ROOM_TTL`].
+const ROOM_GC_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+struct BirdAppState {
+  auth: Arc<Auth>,
+  acls: Arc<Acls>,
+  bus: Arc<dyn RoomBus>,
+  moderator: Arc<Moderator>,
+  connections: Arc<ConnectionConfig>,
+  limits: Arc<ConnectionLimits>,
+  rooms: Arc<RwLock<HashMap<RoomId, Arc<RoomState>>>>,
+}
+
+impl BirdAppState {
+  fn new(
+    auth: Arc<Auth>,
+    bus: Arc<dyn RoomBus>,
+    moderator: Moderator,
+    connections: ConnectionConfig,
+  ) -> Self {
+    Self {
+      auth,
+      acls: Arc::new(Acls::from_env()),
+      bus,
+      moderator: Arc::new(moderator),
+      connections: Arc::new(connections),
+      limits: Arc::new(ConnectionLimits::default()),
+      rooms: Arc::new(RwLock::new(HashMap::new())),
+    }
+  }
+
+  fn room(&self, room: RoomId) -> Option<Arc<RoomState>> {
+    self.rooms.read().unwrap().get(&room).cloned()
+  }
+
+  fn get_or_create(&self, room: RoomId) -> Arc<RoomState> {
+    self.room(room).unwrap_or_else(|| {
+      let mut rooms = self.rooms.write().unwrap();
+      rooms
+        .entry(room)
+        .or_insert_with(|| Arc::new(RoomState::new(room)))
+        .clone()
+    })
+  }
+
+  /// Gets or creates the room and counts a connection to it. This happens under the rooms lock so
+  /// the garbage collector can't remove the room in between.
+  fn connect(&self, room: RoomId) -> Arc<RoomState> {
+    {
+      let rooms = self.rooms.read().unwrap();
+      if let Some(room) = rooms.get(&room) {
+        room.connect();
+        return room.clone();
+      }
+    }
+
+    let mut rooms = self.rooms.write().unwrap();
+    let room = rooms
+      .entry(room)
+      .or_insert_with(|| Arc::new(RoomState::new(room)));
+    room.connect();
+
+    room.clone()
+  }
+
+  fn apply(&self, event: BusEvent) {
+    let room = match event.kind {
+      EventKind::Tweet(_) | EventKind::Join { .. } => Some(self.get_or_create(event.room)),
+      // Nothing to do for rooms this instance has already dropped
+      EventKind::Typing { .. } | EventKind::Leave { .. } => self.room(event.room),
+      EventKind::Closed => self.rooms.write().unwrap().remove(&event.room),
+      EventKind::Acl(acl) => {
+        self.acls.set(event.room, acl);
+        return;
+      }
+    };
+
+    if let Some(room) = room {
+      room.apply(event.kind);
+    }
+  }
+
+  fn remove_expired_rooms(&self) {
+    let mut rooms = self.rooms.write().unwrap();
+    rooms.retain(|id, room| {
+      let expired = room.is_expired(room::EMPTY_ROOM_TTL);
+      if expired {
+        info!("Removing empty room {}", id);
+        self.bus.forget(*id);
+      }
+
+      !expired
+    });
+  }
+}
+
+/// Applies everything published on the bus to the rooms of this instance.
+fn spawn_dispatcher(state: BirdAppState, mut events: Receiver<BusEvent>) {
+  tokio::spawn(async move {
+    loop {
+      match events.recv().await {
+        Ok(event) => state.apply(event),
+        Err(RecvError::Lagged(missed)) => warn!("Chat dispatcher missed {} events", missed),
+        Err(RecvError::Closed) => return,
+      }
+    }
+  });
+}
+
+fn spawn_room_gc(state: BirdAppState) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(ROOM_GC_INTERVAL);
+
+    loop {
+      let _ = interval.tick().await;
+      state.remove_expired_rooms();
+      state.moderator.prune();
+    }
+  });
+}
+
+async fn list_rooms(State(state): State<BirdAppState>) -> Json<Vec<RoomSummary>> {
+  let rooms = state.rooms.read().unwrap();
+
+  let mut summaries = rooms.values().map(|el| el.summary()).collect::<Vec<_>>();
+  summaries.sort_unstable_by_key(|el| el.id);
+
+  Json(summaries)
+}
+
+async fn presence(
+  Path(room): Path<RoomId>,
+  State(state): State<BirdAppState>,
+) -> Result<Json<BTreeMap<String, usize>>, StatusCode> {
+  let room = state.room(room).ok_or(StatusCode::NOT_FOUND)?;
+
+  Ok(Json(room.presence()))
+}
+
+async fn room_stats(
+  Path(room): Path<RoomId>,
+  State(state): State<BirdAppState>,
+) -> Result<Json<RoomStats>, StatusCode> {
+  let room = state.room(room).ok_or(StatusCode::NOT_FOUND)?;
+
+  Ok(Json(room.stats()))
+}
+
+/// Closes the room on every instance.
+async fn close_room(
+  Path(room): Path<RoomId>,
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+) -> Result<StatusCode, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  if state.room(room).is_none() {
+    return Ok(StatusCode::NOT_FOUND);
+  }
+
+  state
+    .bus
+    .publish(BusEvent {
+      room,
+      kind: EventKind::Closed,
+    })
+    .await?;
+
+  Ok(StatusCode::NO_CONTENT)
+}
+
+async fn room_acl(
+  Path(room): Path<RoomId>,
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+) -> Result<Json<RoomAcl>, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  Ok(Json(state.acls.get(room)))
+}
+
+/// Changes the room's ACL on every running instance. Connections already in the room stay, but
+/// can only post if the new ACL lets them.
+async fn set_room_acl(
+  Path(room): Path<RoomId>,
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+  Json(acl): Json<RoomAcl>,
+) -> Result<Json<RoomAcl>, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  state.acls.set(room, acl.clone());
+  state.bus.publish_acl(room, acl.clone()).await?;
+
+  Ok(Json(acl))
+}
+
+async fn moderation_rules(
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+) -> Result<Json<ModerationConfig>, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  Ok(Json(state.moderator.config()))
+}
+
+async fn set_moderation_rules(
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+  Json(config): Json<ModerationConfig>,
+) -> Result<Json<ModerationConfig>, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  state
+    .moderator
+    .set_config(config)
+    .map_err(|e| BadRequest(format!("Invalid banned word: {e}")))?;
+
+  Ok(Json(state.moderator.config()))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ModerationEventsQuery {
+  room: Option<RoomId>,
+  user: Option<String>,
+}
+
+async fn moderation_events(
+  Query(query): Query<ModerationEventsQuery>,
+  headers: HeaderMap,
+  State(state): State<BirdAppState>,
+) -> Result<Json<Vec<ModerationEvent>>, AppError> {
+  let _ = state.auth.require_admin(&headers)?;
+
+  Ok(Json(
+    state.moderator.events(query.room, query.user.as_deref()),
+  ))
+}
+
+async fn schema() -> impl IntoResponse {
+  (
+    [(header::CONTENT_TYPE, "application/schema+json")],
+    protocol::SCHEMA,
+  )
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TweetQuery {
+  /// Replay every stored tweet with a larger sequence id before going live.
+  since: Option<Seq>,
+  /// Protocol version, legacy clients leave it out.
+  v: Option<u8>,
+  /// Session token, for clients that can't set the `Authorization` header.
+  token: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct TweetPath {
+  room_id: RoomId,
+  /// Only trusted without a token if anonymous users are allowed, otherwise it has to match the
+  /// token.
+  user: Option<String>,
+}
+
+/// Works out who is connecting from the token, falling back to the user in the path for
+/// anonymous users.
+fn identify(
+  state: &BirdAppState,
+  headers: &HeaderMap,
+  token: Option<&str>,
+  user: Option<String>,
+) -> Result<Identity, AuthError> {
+  match state.auth.authenticate(headers, token) {
+    Ok(claims) => {
+      if user.is_some_and(|el| el != claims.sub) {
+        return Err(AuthError::Forbidden("Token was issued to another user"));
+      }
+
+      Ok(claims.into())
+    }
+    Err(AuthError::Missing) if state.connections.allow_anonymous => user
+      .map(|user| Identity {
+        user,
+        admin: false,
+        anonymous: true,
+      })
+      .ok_or(AuthError::Missing),
+    Err(e) => Err(e),
+  }
+}
+
+/// The address the client connected from. Behind a trusted proxy that is the last address in
+/// `X-Forwarded-For`, the one the proxy added, as anything before it came from the client.
+fn client_ip(
+  config: &ConnectionConfig,
+  headers: &HeaderMap,
+  connect_info: Option<ConnectInfo<SocketAddr>>,
+) -> Option<IpAddr> {
+  if config.trust_forwarded_for {
+    return headers
+      .get_all("x-forwarded-for")
+      .iter()
+      .filter_map(|el| el.to_str().ok())
+      .flat_map(|el| el.split(','))
+      .last()
+      .and_then(|el| el.trim().parse().ok());
+  }
+
+  connect_info.map(|el| el.0.ip())
+}
+
+async fn tweet(
+  ws: WebSocketUpgrade,
+  Path(TweetPath {
+    room_id: room,
+    user,
+  }): Path<TweetPath>,
+  Query(query): Query<TweetQuery>,
+  headers: HeaderMap,
+  connect_info: Option<ConnectInfo<SocketAddr>>,
+  State(state): State<BirdAppState>,
+) -> Response {
+  if let Some(v) = query.v.filter(|v| *v != protocol::VERSION) {
+    return (
+      StatusCode::BAD_REQUEST,
+      format!("Unsupported protocol version {v}"),
+    )
+      .into_response();
+  }
+
+  let identity = match identify(&state, &headers, query.token.as_deref(), user) {
+    Ok(identity) => identity,
+    Err(e) => return e.into_response(),
+  };
+  if !state.acls.can_join(room, &identity) {
+    return AuthError::Forbidden("Room is invite-only").into_response();
+  }
+
+  let ip = client_ip(&state.connections, &headers, connect_info);
+  let Some(permit) = state.limits.acquire(&state.connections, room, ip) else {
+    return (StatusCode::TOO_MANY_REQUESTS, "Too many connections").into_response();
+  };
+
+  ws.on_upgrade(move |c| handle_tweet(c, room, identity, query, Arc::new(state), permit))
+}
+
+/// Handles a frame sent by the client, returning what to reply with.
+async fn handle_frame(
+  state: &BirdAppState,
+  room: RoomId,
+  identity: &Identity,
+  text: &str,
+  version: Option<u8>,
+) -> Vec<ServerFrame> {
+  let user = identity.user.as_str();
+
+  match protocol::parse(text, version) {
+    // Checked on every frame so ACL changes apply to open connections too
+    Ok(ClientFrame::Message { .. }) if !state.acls.can_post(room, identity) => {
+      vec![ServerFrame::error(
+        ErrorCode::Forbidden,
+        "Only members can post in this room",
+      )]
+    }
+    Ok(ClientFrame::Typing) if !state.acls.can_post(room, identity) => vec![],
+    Ok(ClientFrame::Message { message, id }) => {
+      info!("Parsed {:?}", message);
+
+      match state.moderator.check(room, user, message) {
+        Verdict::Publish(message) => {
+          match state
+            .bus
+            .publish_tweet(room, user.to_string(), message)
+            .await
+          {
+            Ok(seq) => vec![ServerFrame::Ack { seq, id }],
+            Err(e) => {
+              warn!("Failed to publish tweet: {:?}", e);
+              vec![ServerFrame::error(
+                ErrorCode::Unavailable,
+                "Message could not be sent",
+              )]
+            }
+          }
+        }
+        // Looks like any other tweet to the sender, but nobody else gets it
+        Verdict::ShadowBanned(message) => {
+          let seq = state.room(room).map_or(0, |el| el.last_seq());
+          let tweet = Tweet {
+            seq,
+            user: user.to_string(),
+            message,
+          };
+
+          vec![ServerFrame::Ack { seq, id }, ServerFrame::Message(tweet)]
+        }
+        Verdict::Rejected { code, reason } => {
+          warn!("Rejected tweet from {}: {}", user, reason);
+          vec![ServerFrame::error(code, reason)]
+        }
+      }
+    }
+    Ok(ClientFrame::Typing) => {
+      let typing = BusEvent {
+        room,
+        kind: EventKind::Typing {
+          user: user.to_string(),
+        },
+      };
+      if let Err(e) = state.bus.publish(typing).await {
+        warn!("Failed to publish typing: {:?}", e);
+      }
+
+      vec![]
+    }
+    Err(e) => {
+      warn!("Failed to parse frame: {:?}", e);
+      vec![e]
+    }
+  }
+}
+
+async fn handle_tweet(
+  socket: WebSocket,
+  room: i32,
+  identity: Identity,
+  query: TweetQuery,
+  state: Arc<BirdAppState>,
+  _permit: ConnectionPermit,
+) {
+  let user = identity.user.clone();
+  let (sink, mut receiver) = socket.split();
+  let version = query.v;
+
+  let room_state = state.connect(room);
+
+  // Subscribe before looking at the history so nothing falls in between, anything seen twice is
+  // skipped by its sequence id.
+  let room_receiver = room_state.subscribe();
+  let last_seq = query.since.unwrap_or_else(|| room_state.last_seq());
+
+  let queue = Arc::new(OutboundQueue::new(
+    state.connections.outbound_queue,
+    state.connections.slow_consumer,
+  ));
+
+  let bus = state.bus.clone();
+  let event = |kind| BusEvent { room, kind };
+  if let Err(e) = bus
+    .publish(event(EventKind::Join { user: user.clone() }))
+    .await
+  {
+    warn!("Failed to announce {} joined: {:?}", user, e);
+  }
+
+  let mut write = {
+    let bus = bus.clone();
+    let room_state = room_state.clone();
+    let user = user.clone();
+
+    tokio::spawn(connection::write(
+      sink,
+      queue.clone(),
+      state.connections.ping_interval(),
+      move |frame| {
+        let text = frame.render(version)?;
+        if matches!(frame, ServerFrame::Message(_)) {
+          bus.add_view();
+          room_state.record_view(&user);
+        }
+
+        Some(text)
+      },
+    ))
+  };
+
+  let mut read = {
+    let state = state.clone();
+    let queue = queue.clone();
+    let idle_timeout = state.connections.idle_timeout();
+
+    tokio::spawn(async move {
+      loop {
+        let Ok(msg) = tokio::time::timeout(idle_timeout, receiver.next()).await else {
+          queue.close(CloseReason::Idle);
+          return;
+        };
+
+        // Pongs and anything else the client sends only count as activity
+        let Some(Ok(msg)) = msg else {
+          return;
+        };
+        let Text(text) = &msg else {
+          continue;
+        };
+
+        for reply in handle_frame(&state, room, &identity, text, version).await {
+          if !queue.push(reply) {
+            return;
+          }
+        }
+      }
+    })
+  };
+
+  // Without `since` there is nothing to replay and catching up is only for a lagging receiver
+  let mut forward = tokio::spawn(forward(
+    room_state.clone(),
+    room_receiver,
+    queue.clone(),
+    last_seq,
+    query.since.is_some(),
+  ));
+
+  // The writer sends the close frame, so it is the one waited on once the others are done
+  tokio::select! {
+    _ = (&mut write) => {}
+    _ = (&mut read) => {
+      queue.close(CloseReason::ClientGone);
+      let _ = write.await;
+    }
+    _ = (&mut forward) => {
+      let _ = write.await;
+    }
+  };
+  read.abort();
+  forward.abort();
+
+  room_state.disconnect();
+  if let Err(e) = bus.publish(event(EventKind::Leave { user })).await {
+    warn!("Failed to announce a user left: {:?}", e);
+  }
+}
+
+/// Queues everything happening in the room for the connection, until the room closes.
+async fn forward(
+  room_state: Arc<RoomState>,
+  mut room_receiver: Receiver<ServerFrame>,
+  queue: Arc<OutboundQueue>,
+  mut last_seq: Seq,
+  mut catch_up: bool,
+) {
+  let closed = room_state.closed();
+
+  loop {
+    if catch_up {
+      catch_up = false;
+      let (missed, lost) = room_state.since(last_seq);
+
+      if lost > 0 && !queue.push(ServerFrame::Lost { count: lost }) {
+        return;
+      }
+
+      for tweet in missed {
+        last_seq = last_seq.max(tweet.seq);
+        if !queue.push(ServerFrame::Message(tweet)) {
+          return;
+        }
+      }
+    }
+
+    let frame = tokio::select! {
+      () = closed.cancelled() => {
+        let reason = CloseReason::RoomClosed;
+        let _ = queue.push(ServerFrame::Closed { reason: reason.to_string() });
+        queue.close(reason);
+        return;
+      }
+      frame = room_receiver.recv() => match frame {
+        Ok(ServerFrame::Message(tweet)) if tweet.seq <= last_seq => continue,
+        Ok(frame) => frame,
+        Err(RecvError::Lagged(_)) => {
+          catch_up = true;
+          continue;
+        }
+        Err(RecvError::Closed) => {
+          queue.close(CloseReason::ServerError);
+          return;
+        }
+      },
+    };
+
+    if let ServerFrame::Message(tweet) = &frame {
+      last_seq = last_seq.max(tweet.seq);
+    }
+
+    if !queue.push(frame) {
+      return;
+    }
+  }
+}
@@ -0,0 +1,422 @@
+//! Rules every tweet goes through before it is published.
+//!
+//! Rules run in order. When one is broken its action decides what happens: `reject` refuses the
+//! tweet, `redact` makes the offending part harmless and moves on to the next rule, and
+//! `shadow_ban` keeps the tweet and everything the user sends for a while visible only to them.
+//! Rate limits, floods and shadow bans are tracked per instance.
+
+use std::{
+  collections::{HashMap, VecDeque},
+  sync::{Mutex, RwLock},
+  time::{Duration, Instant},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{protocol::ErrorCode, room::RoomId};
+use crate::days::day_14::escape_html;
+
+/// How many moderation events are kept for the admin endpoint.
+const EVENTS_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationConfig {
+  pub rules: Vec<Rule>,
+  /// How long a shadow ban lasts, forever when it is too long to tell when it ends.
+  #[serde(default = "default_shadow_ban_secs")]
+  pub shadow_ban_secs: u64,
+}
+
+const fn default_shadow_ban_secs() -> u64 {
+  10 * 60
+}
+
+impl Default for ModerationConfig {
+  fn default() -> Self {
+    Self {
+      rules: vec![Rule::MaxLength {
+        graphemes: 128,
+        action: Action::Reject,
+      }],
+      shadow_ban_secs: default_shadow_ban_secs(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+pub enum Rule {
+  /// Redacting truncates the tweet.
+  MaxLength { graphemes: usize, action: Action },
+  /// Whole words, ignoring case. Redacting masks them.
+  BannedWords { words: Vec<String>, action: Action },
+  /// At most `messages` tweets per user every `per_secs`. Redacting replaces the whole tweet.
+  RateLimit {
+    messages: usize,
+    per_secs: u64,
+    action: Action,
+  },
+  /// The same tweet `repeats` times in a row within `within_secs`. Redacting replaces the whole
+  /// tweet.
+  Flood {
+    repeats: usize,
+    within_secs: u64,
+    action: Action,
+  },
+  /// Any HTML special character. Redacting escapes them.
+  EscapeHtml { action: Action },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+  Reject,
+  Redact,
+  ShadowBan,
+}
+
+impl Rule {
+  const fn name(&self) -> &'static str {
+    match self {
+      Self::MaxLength { .. } => "max_length",
+      Self::BannedWords { .. } => "banned_words",
+      Self::RateLimit { .. } => "rate_limit",
+      Self::Flood { .. } => "flood",
+      Self::EscapeHtml { .. } => "escape_html",
+    }
+  }
+
+  const fn action(&self) -> Action {
+    match self {
+      Self::MaxLength { action, .. }
+      | Self::BannedWords { action, .. }
+      | Self::RateLimit { action, .. }
+      | Self::Flood { action, .. }
+      | Self::EscapeHtml { action } => *action,
+    }
+  }
+
+  const fn error_code(&self) -> ErrorCode {
+    match self {
+      Self::MaxLength { .. } => ErrorCode::MessageTooLong,
+      _ => ErrorCode::Rejected,
+    }
+  }
+}
+
+/// A rule along with what it needs precompiled.
+#[derive(Debug)]
+struct CompiledRule {
+  rule: Rule,
+  banned_words: Option<Regex>,
+}
+
+impl CompiledRule {
+  fn new(rule: Rule) -> Result<Self, regex::Error> {
+    let banned_words = match &rule {
+      Rule::BannedWords { words, .. } if !words.is_empty() => {
+        let words = words
+          .iter()
+          .map(|el| regex::escape(el))
+          .collect::<Vec<_>>()
+          .join("|");
+
+        Some(Regex::new(&format!(r"(?i)\b(?:{words})\b"))?)
+      }
+      _ => None,
+    };
+
+    Ok(Self { rule, banned_words })
+  }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModerationEvent {
+  pub at: String,
+  pub room: RoomId,
+  pub user: String,
+  pub rule: &'static str,
+  pub action: Action,
+  /// The tweet as the user sent it.
+  pub message: String,
+}
+
+#[derive(Debug)]
+pub enum Verdict {
+  Publish(String),
+  /// Only the sender gets to see it.
+  ShadowBanned(String),
+  Rejected {
+    code: ErrorCode,
+    reason: String,
+  },
+}
+
+#[derive(Debug, Default)]
+struct UserActivity {
+  sent: VecDeque<Instant>,
+  /// The last tweet, lowercased, and when the streak of sending it started.
+  last_message: Option<(String, Instant)>,
+  repeats: usize,
+  shadow_ban: Option<ShadowBan>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ShadowBan {
+  Until(Instant),
+  Forever,
+}
+
+impl ShadowBan {
+  fn active(self, now: Instant) -> bool {
+    match self {
+      Self::Until(end) => end > now,
+      Self::Forever => true,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Moderator {
+  config: RwLock<(ModerationConfig, Vec<CompiledRule>)>,
+  activity: Mutex<HashMap<String, UserActivity>>,
+  events: Mutex<VecDeque<ModerationEvent>>,
+}
+
+impl Moderator {
+  /// Reads the config as JSON from `CHAT_MODERATION`, falling back to the default.
+  pub fn from_env() -> Self {
+    let config = std::env::var("CHAT_MODERATION")
+      .ok()
+      .and_then(|el| {
+        serde_json::from_str::<ModerationConfig>(&el)
+          .map_err(|e| warn!("Ignoring invalid CHAT_MODERATION: {:?}", e))
+          .ok()
+      })
+      .unwrap_or_default();
+
+    let (config, compiled) = match compile(&config) {
+      Ok(compiled) => (config, compiled),
+      Err(e) => {
+        warn!(
+          "Ignoring CHAT_MODERATION with an invalid banned word: {:?}",
+          e
+        );
+        let config = ModerationConfig::default();
+        let compiled = compile(&config).unwrap_or_default();
+
+        (config, compiled)
+      }
+    };
+
+    Self {
+      config: RwLock::new((config, compiled)),
+      activity: Mutex::new(HashMap::new()),
+      events: Mutex::new(VecDeque::with_capacity(EVENTS_SIZE)),
+    }
+  }
+
+  pub fn config(&self) -> ModerationConfig {
+    self.config.read().unwrap().0.clone()
+  }
+
+  pub fn set_config(&self, config: ModerationConfig) -> Result<(), regex::Error> {
+    let compiled = compile(&config)?;
+    *self.config.write().unwrap() = (config, compiled);
+
+    Ok(())
+  }
+
+  /// Most recent events first, optionally only those of one room or user.
+  pub fn events(&self, room: Option<RoomId>, user: Option<&str>) -> Vec<ModerationEvent> {
+    self
+      .events
+      .lock()
+      .unwrap()
+      .iter()
+      .rev()
+      .filter(|el| room.is_none_or(|room| room == el.room))
+      .filter(|el| user.is_none_or(|user| user == el.user))
+      .cloned()
+      .collect()
+  }
+
+  /// Runs the tweet through every rule.
+  pub fn check(&self, room: RoomId, user: &str, message: String) -> Verdict {
+    let now = Instant::now();
+    let config = self.config.read().unwrap();
+    let (config, rules) = &*config;
+
+    let mut activity = self.activity.lock().unwrap();
+    let user_activity = activity.entry(user.to_string()).or_default();
+
+    // Repeats are counted on what was sent, not what was left after redacting
+    let (repeats, since_first) = user_activity.record(&message, now, lookback(config));
+
+    if user_activity.shadow_ban.is_some_and(|el| el.active(now)) {
+      return Verdict::ShadowBanned(message);
+    }
+
+    let original = message.clone();
+    let mut message = message;
+
+    for CompiledRule { rule, banned_words } in rules {
+      let redacted = match rule {
+        Rule::MaxLength { graphemes, .. } => (message.graphemes(true).count() > *graphemes)
+          .then(|| message.graphemes(true).take(*graphemes).collect()),
+        Rule::BannedWords { .. } => banned_words
+          .as_ref()
+          .filter(|el| el.is_match(&message))
+          .map(|el| {
+            el.replace_all(&message, |caps: &regex::Captures| {
+              "*".repeat(caps[0].graphemes(true).count())
+            })
+            .into_owned()
+          }),
+        Rule::RateLimit {
+          messages, per_secs, ..
+        } => {
+          let window = Duration::from_secs(*per_secs);
+          let sent = user_activity
+            .sent
+            .iter()
+            .filter(|el| now.duration_since(**el) < window)
+            .count();
+
+          (sent > *messages).then(|| "[redacted]".to_string())
+        }
+        Rule::Flood {
+          repeats: max_repeats,
+          within_secs,
+          ..
+        } => (repeats >= *max_repeats && since_first < Duration::from_secs(*within_secs))
+          .then(|| "[redacted]".to_string()),
+        Rule::EscapeHtml { .. } => {
+          let escaped = escape_html(&message);
+          (escaped != message).then_some(escaped)
+        }
+      };
+
+      let Some(redacted) = redacted else {
+        continue;
+      };
+
+      let action = rule.action();
+      self.record_event(room, user, rule.name(), action, &original);
+
+      match action {
+        Action::Reject => {
+          return Verdict::Rejected {
+            code: rule.error_code(),
+            reason: format!("Message broke the {} rule", rule.name()),
+          }
+        }
+        Action::Redact => message = redacted,
+        Action::ShadowBan => {
+          user_activity.shadow_ban = Some(
+            now
+              .checked_add(Duration::from_secs(config.shadow_ban_secs))
+              .map_or(ShadowBan::Forever, ShadowBan::Until),
+          );
+
+          return Verdict::ShadowBanned(message);
+        }
+      }
+    }
+
+    Verdict::Publish(message)
+  }
+
+  fn record_event(
+    &self,
+    room: RoomId,
+    user: &str,
+    rule: &'static str,
+    action: Action,
+    message: &str,
+  ) {
+    let mut events = self.events.lock().unwrap();
+    if events.len() == EVENTS_SIZE {
+      let _ = events.pop_front();
+    }
+
+    events.push_back(ModerationEvent {
+      at: chrono::Utc::now().to_rfc3339(),
+      room,
+      user: user.to_string(),
+      rule,
+      action,
+      message: message.to_string(),
+    });
+  }
+
+  /// Forgets users that have been quiet for longer than any rule looks back.
+  pub fn prune(&self) {
+    let now = Instant::now();
+    let lookback = lookback(&self.config.read().unwrap().0);
+
+    self.activity.lock().unwrap().retain(|_, el| {
+      el.shadow_ban.is_some_and(|el| el.active(now))
+        || el
+          .sent
+          .back()
+          .is_some_and(|el| now.duration_since(*el) < lookback)
+    });
+  }
+}
+
+impl UserActivity {
+  /// Records a tweet, returning how many times in a row it has now been sent and how long ago
+  /// the first of those was.
+  fn record(&mut self, message: &str, now: Instant, lookback: Duration) -> (usize, Duration) {
+    while self
+      .sent
+      .front()
+      .is_some_and(|el| now.duration_since(*el) >= lookback)
+    {
+      let _ = self.sent.pop_front();
+    }
+    self.sent.push_back(now);
+
+    let message = message.trim().to_lowercase();
+    match &self.last_message {
+      Some((last, first_at)) if *last == message => {
+        self.repeats += 1;
+        (self.repeats, now.duration_since(*first_at))
+      }
+      _ => {
+        self.last_message = Some((message, now));
+        self.repeats = 1;
+        (1, Duration::ZERO)
+      }
+    }
+  }
+}
+
+/// The furthest back any rule looks at what a user sent.
+fn lookback(config: &ModerationConfig) -> Duration {
+  let secs = config
+    .rules
+    .iter()
+    .map(|el| match el {
+      Rule::RateLimit { per_secs, .. } => *per_secs,
+      Rule::Flood { within_secs, .. } => *within_secs,
+      _ => 0,
+    })
+    .max()
+    .unwrap_or_default();
+
+  Duration::from_secs(secs)
+}
+
+fn compile(config: &ModerationConfig) -> Result<Vec<CompiledRule>, regex::Error> {
+  config
+    .rules
+    .iter()
+    .cloned()
+    .map(CompiledRule::new)
+    .collect()
+}
@@ -0,0 +1,148 @@
+//! The frames exchanged over `/19/ws/room/:room_id`.
+//!
+//! Clients that connect with `?v=1` talk in envelopes tagged with the protocol version and a
+//! frame `type`, as described by [`SCHEMA`]. Clients that connect without a version get the
+//! original protocol: they send `{"message": ...}` and only receive tweets.
+
+use serde::{Deserialize, Serialize};
+
+use super::Seq;
+
+pub const VERSION: u8 = 1;
+
+/// JSON schema of every frame, served at `/19/protocol/schema.json` for client developers.
+pub const SCHEMA: &str = include_str!("../../../assets/day_19_protocol.schema.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tweet {
+  pub seq: Seq,
+  pub user: String,
+  pub message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+  Message {
+    message: String,
+    /// Echoed back in the [`ServerFrame::Ack`] so clients can match it to what they sent.
+    #[serde(default)]
+    id: Option<String>,
+  },
+  Typing,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClientEnvelope {
+  v: u8,
+  #[serde(flatten)]
+  frame: ClientFrame,
+}
+
+/// The only thing legacy clients ever send.
+#[derive(Debug, Deserialize)]
+struct LegacyFrame {
+  message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+  Join {
+    user: String,
+  },
+  Leave {
+    user: String,
+  },
+  Message(Tweet),
+  Typing {
+    user: String,
+  },
+  Ack {
+    seq: Seq,
+    id: Option<String>,
+  },
+  /// Tweets that were sent while the client lagged behind or was away and are no longer stored.
+  Lost {
+    count: u64,
+  },
+  Error {
+    code: ErrorCode,
+    message: String,
+  },
+  /// The room was closed, sent right before the connection is.
+  Closed {
+    reason: String,
+  },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+  InvalidFrame,
+  UnsupportedVersion,
+  MessageTooLong,
+  /// Refused by one of the moderation rules.
+  Rejected,
+  /// The message was valid but could not be delivered, sending it again may work.
+  Unavailable,
+  /// The room's ACL doesn't let the user post.
+  Forbidden,
+}
+
+impl ServerFrame {
+  pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+    Self::Error {
+      code,
+      message: message.into(),
+    }
+  }
+
+  /// Renders the frame for a client speaking `version`, or `None` if a legacy client has no use
+  /// for it.
+  pub fn render(&self, version: Option<u8>) -> Option<String> {
+    #[derive(Serialize)]
+    struct ServerEnvelope<'a> {
+      v: u8,
+      #[serde(flatten)]
+      frame: &'a ServerFrame,
+    }
+
+    let res = match (version, self) {
+      (Some(v), frame) => serde_json::to_string(&ServerEnvelope { v, frame }),
+      (None, Self::Message(tweet)) => serde_json::to_string(tweet),
+      (None, Self::Lost { count }) => Ok(serde_json::json!({ "lost": count }).to_string()),
+      (None, _) => return None,
+    };
+
+    res.ok()
+  }
+}
+
+/// Parses a frame sent by a client speaking `version`. The message itself is checked later by
+/// [`super::moderation`].
+pub fn parse(text: &str, version: Option<u8>) -> Result<ClientFrame, ServerFrame> {
+  let invalid = |e: serde_json::Error| ServerFrame::error(ErrorCode::InvalidFrame, e.to_string());
+
+  let frame = if let Some(version) = version {
+    let envelope = serde_json::from_str::<ClientEnvelope>(text).map_err(invalid)?;
+
+    if envelope.v != version {
+      return Err(ServerFrame::error(
+        ErrorCode::UnsupportedVersion,
+        format!(
+          "Connected with version {version} but got a version {} frame",
+          envelope.v
+        ),
+      ));
+    }
+
+    envelope.frame
+  } else {
+    let LegacyFrame { message } = serde_json::from_str(text).map_err(invalid)?;
+
+    ClientFrame::Message { message, id: None }
+  };
+
+  Ok(frame)
+}
@@ -0,0 +1,276 @@
+//! A chat room: its tweet history, who is connected to it and how much they have read and sent.
+
+use std::{
+  collections::{BTreeMap, HashMap, VecDeque},
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+  bus::EventKind,
+  protocol::{ServerFrame, Tweet},
+  Seq,
+};
+
+/// How many tweets each room keeps around for replaying to reconnecting users.
+const HISTORY_SIZE: usize = 1000;
+
+/// How long a room without connections is kept, so users reconnecting shortly after can still
+/// replay its history.
+pub const EMPTY_ROOM_TTL: Duration = Duration::from_secs(5 * 60);
+
+pub type RoomId = i32;
+
+#[derive(Debug)]
+pub struct RoomState {
+  pub id: RoomId,
+  sender: Sender<ServerFrame>,
+  history: Mutex<History>,
+  presence: Mutex<Presence>,
+  /// Cancelled when the room is closed, which ends every connection to it.
+  closed: CancellationToken,
+}
+
+#[derive(Debug)]
+struct History {
+  tweets: VecDeque<Tweet>,
+  last_seq: Seq,
+}
+
+#[derive(Debug, Default)]
+struct Presence {
+  users: HashMap<String, UserStats>,
+  /// When the last connection left, `None` while anyone is connected.
+  empty_since: Option<Instant>,
+  /// Connections to this instance that may not have been announced on the bus yet.
+  local_connections: usize,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct UserStats {
+  pub connections: usize,
+  /// Tweets delivered to this user by this instance.
+  pub views: u64,
+  /// Tweets sent by this user.
+  pub messages: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomSummary {
+  pub id: RoomId,
+  /// Users with at least one open connection.
+  pub members: usize,
+  pub connections: usize,
+  pub views: u64,
+  pub messages: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomStats {
+  #[serde(flatten)]
+  pub summary: RoomSummary,
+  pub users: BTreeMap<String, UserStats>,
+}
+
+impl RoomState {
+  pub fn new(id: RoomId) -> Self {
+    Self {
+      id,
+      sender: broadcast::channel(100).0,
+      history: Mutex::new(History {
+        tweets: VecDeque::with_capacity(HISTORY_SIZE),
+        last_seq: 0,
+      }),
+      presence: Mutex::new(Presence {
+        users: HashMap::new(),
+        empty_since: Some(Instant::now()),
+        local_connections: 0,
+      }),
+      closed: CancellationToken::new(),
+    }
+  }
+
+  pub fn subscribe(&self) -> Receiver<ServerFrame> {
+    self.sender.subscribe()
+  }
+
+  /// Applies an event delivered by the bus, telling local connections about it.
+  pub fn apply(&self, event: EventKind) {
+    match event {
+      EventKind::Tweet(tweet) => self.record(tweet),
+      EventKind::Typing { user } => self.announce(ServerFrame::Typing { user }),
+      EventKind::Join { user } => self.joined(user),
+      EventKind::Leave { user } => self.left(user),
+      EventKind::Closed => self.close(),
+      // Kept by the instance's ACLs, not the room
+      EventKind::Acl(_) => {}
+    }
+  }
+
+  /// Stores the tweet and sends it to everyone in the room. The history lock is held while
+  /// sending so receivers always see tweets in sequence order.
+  fn record(&self, tweet: Tweet) {
+    self.presence.lock().unwrap().user(&tweet.user).messages += 1;
+
+    let mut history = self.history.lock().unwrap();
+    if tweet.seq <= history.last_seq {
+      return;
+    }
+    history.last_seq = tweet.seq;
+
+    if history.tweets.len() == HISTORY_SIZE {
+      let _ = history.tweets.pop_front();
+    }
+    history.tweets.push_back(tweet.clone());
+
+    // Nobody listening is fine, the tweet is still in the history
+    let _ = self.sender.send(ServerFrame::Message(tweet));
+  }
+
+  /// Sends a frame that is not worth storing, like a typing notification.
+  pub fn announce(&self, frame: ServerFrame) {
+    let _ = self.sender.send(frame);
+  }
+
+  pub fn last_seq(&self) -> Seq {
+    self.history.lock().unwrap().last_seq
+  }
+
+  /// Returns the stored tweets after `since`, along with how many newer tweets are no longer
+  /// stored.
+  pub fn since(&self, since: Seq) -> (Vec<Tweet>, u64) {
+    let history = self.history.lock().unwrap();
+
+    let tweets = history
+      .tweets
+      .iter()
+      .filter(|el| el.seq > since)
+      .cloned()
+      .collect::<Vec<_>>();
+
+    let first_stored = tweets.first().map_or(history.last_seq + 1, |el| el.seq);
+    let lost = first_stored.saturating_sub(since + 1);
+
+    (tweets, lost)
+  }
+
+  /// Counts a connection to this instance, which keeps the room from being garbage-collected.
+  pub fn connect(&self) {
+    self.presence.lock().unwrap().local_connections += 1;
+  }
+
+  pub fn disconnect(&self) {
+    let mut presence = self.presence.lock().unwrap();
+    presence.local_connections = presence.local_connections.saturating_sub(1);
+  }
+
+  /// Registers a connection on any instance, announcing the user if it is their first one.
+  fn joined(&self, user: String) {
+    let mut presence = self.presence.lock().unwrap();
+    presence.empty_since = None;
+
+    let stats = presence.user(&user);
+    stats.connections += 1;
+
+    if stats.connections == 1 {
+      self.announce(ServerFrame::Join { user });
+    }
+  }
+
+  /// Unregisters a connection, announcing the user left once their last one is gone.
+  fn left(&self, user: String) {
+    let mut presence = self.presence.lock().unwrap();
+
+    let stats = presence.user(&user);
+    stats.connections = stats.connections.saturating_sub(1);
+
+    if stats.connections == 0 {
+      self.announce(ServerFrame::Leave { user });
+    }
+
+    if presence.users.values().all(|el| el.connections == 0) {
+      presence.empty_since = Some(Instant::now());
+    }
+  }
+
+  pub fn record_view(&self, user: &str) {
+    self.presence.lock().unwrap().user(user).views += 1;
+  }
+
+  pub fn reset_views(&self) {
+    let mut presence = self.presence.lock().unwrap();
+    for stats in presence.users.values_mut() {
+      stats.views = 0;
+    }
+  }
+
+  /// Users currently connected, with how many connections each has open.
+  pub fn presence(&self) -> BTreeMap<String, usize> {
+    let presence = self.presence.lock().unwrap();
+
+    presence
+      .users
+      .iter()
+      .filter(|(_, stats)| stats.connections > 0)
+      .map(|(user, stats)| (user.clone(), stats.connections))
+      .collect()
+  }
+
+  pub fn summary(&self) -> RoomSummary {
+    let presence = self.presence.lock().unwrap();
+
+    RoomSummary {
+      id: self.id,
+      members: presence
+        .users
+        .values()
+        .filter(|el| el.connections > 0)
+        .count(),
+      connections: presence.users.values().map(|el| el.connections).sum(),
+      views: presence.users.values().map(|el| el.views).sum(),
+      messages: presence.users.values().map(|el| el.messages).sum(),
+    }
+  }
+
+  pub fn stats(&self) -> RoomStats {
+    let users = self
+      .presence
+      .lock()
+      .unwrap()
+      .users
+      .iter()
+      .map(|(user, stats)| (user.clone(), stats.clone()))
+      .collect();
+
+    RoomStats {
+      summary: self.summary(),
+      users,
+    }
+  }
+
+  /// Whether the room has had no connections on any instance for longer than `ttl`.
+  pub fn is_expired(&self, ttl: Duration) -> bool {
+    let presence = self.presence.lock().unwrap();
+
+    presence.local_connections == 0 && presence.empty_since.is_some_and(|el| el.elapsed() >= ttl)
+  }
+
+  /// Ends every connection to the room, each telling its client why.
+  fn close(&self) {
+    self.closed.cancel();
+  }
+
+  pub fn closed(&self) -> CancellationToken {
+    self.closed.clone()
+  }
+}
+
+impl Presence {
+  fn user(&mut self, user: &str) -> &mut UserStats {
+    self.users.entry(user.to_string()).or_default()
+  }
+}
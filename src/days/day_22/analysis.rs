@@ -0,0 +1,152 @@
+//! What else the star map can tell, besides the shortest way through it.
+
+use pathfinding::{
+  directed::{
+    bfs::bfs_reach, strongly_connected_components::strongly_connected_components, yen::yen,
+  },
+  undirected::{connected_components::connected_components, kruskal::kruskal_indices},
+};
+use serde::Serialize;
+
+use super::graph::{Graph, Length, Portal, Route};
+
+#[derive(Serialize, Debug)]
+pub struct SpanningEdge {
+  pub portal: usize,
+  #[serde(flatten)]
+  pub edge: Portal,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SpanningForest {
+  pub edges: Vec<SpanningEdge>,
+  pub length: f64,
+  /// One tree per group of stars the portals connect, so 1 when they span the whole map.
+  pub trees: usize,
+}
+
+/// Each group sorted, and the groups in order of their smallest star.
+fn sorted(groups: impl IntoIterator<Item = impl IntoIterator<Item = usize>>) -> Vec<Vec<usize>> {
+  let mut groups = groups
+    .into_iter()
+    .map(|el| {
+      let mut group = el.into_iter().collect::<Vec<_>>();
+      group.sort_unstable();
+      group
+    })
+    .collect::<Vec<_>>();
+  groups.sort_unstable();
+  groups
+}
+
+impl Graph {
+  fn targets(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+    self.edges(node).iter().map(|el| el.to)
+  }
+
+  pub fn reachable(&self, from: usize) -> Vec<usize> {
+    let mut reached = bfs_reach(from, |&node| self.targets(node)).collect::<Vec<_>>();
+    reached.sort_unstable();
+    reached
+  }
+
+  /// The groups of stars joined by portals, whichever way they go.
+  pub fn components(&self) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![Vec::new(); self.nodes.len()];
+    for portal in &self.portals {
+      neighbours[portal.from].push(portal.to);
+      neighbours[portal.to].push(portal.from);
+    }
+
+    let stars = (0..self.nodes.len()).collect::<Vec<_>>();
+    sorted(connected_components(&stars, |&node| {
+      neighbours[node].iter().copied()
+    }))
+  }
+
+  pub fn strongly_connected(&self) -> Vec<Vec<usize>> {
+    let stars = (0..self.nodes.len()).collect::<Vec<_>>();
+    sorted(strongly_connected_components(&stars, |&node| {
+      self.targets(node).collect::<Vec<_>>()
+    }))
+  }
+
+  /// Some cycle, starting and ending on the same star. A portal isn't taken back the way it
+  /// came when portals go both ways.
+  pub fn find_cycle(&self) -> Option<Vec<usize>> {
+    let mut done = vec![false; self.nodes.len()];
+    let mut on_stack = vec![None; self.nodes.len()];
+
+    for root in 0..self.nodes.len() {
+      if done[root] {
+        continue;
+      }
+
+      // The star, the portal it was reached through and the next of its edges to follow
+      let mut stack = vec![(root, None, 0)];
+      on_stack[root] = Some(0);
+
+      while let Some(top) = stack.last_mut() {
+        let (node, entered) = (top.0, top.1);
+        let Some(edge) = self.edges(node).get(top.2) else {
+          let _ = stack.pop();
+          on_stack[node] = None;
+          done[node] = true;
+          continue;
+        };
+        top.2 += 1;
+
+        if !self.directed && entered == Some(edge.portal) {
+          continue;
+        }
+        if let Some(pos) = on_stack[edge.to] {
+          let mut cycle = stack[pos..].iter().map(|el| el.0).collect::<Vec<_>>();
+          cycle.push(edge.to);
+          return Some(cycle);
+        }
+        if !done[edge.to] {
+          on_stack[edge.to] = Some(stack.len());
+          stack.push((edge.to, Some(edge.portal), 0));
+        }
+      }
+    }
+
+    None
+  }
+
+  /// Up to `k` shortest routes that don't visit a star twice, shortest first.
+  pub fn k_shortest(&self, start: usize, goal: usize, k: usize) -> Vec<Route> {
+    yen(
+      &start,
+      |&node| self.successors(node).collect::<Vec<_>>(),
+      |&node| node == goal,
+      k,
+    )
+    .into_iter()
+    .map(|(path, _)| self.route_along(path))
+    .collect()
+  }
+
+  /// The shortest set of portals joining everything they can, taking them as going both ways.
+  pub fn spanning_forest(&self) -> SpanningForest {
+    let edges = self
+      .portals
+      .iter()
+      .enumerate()
+      .map(|(i, el)| (el.from, el.to, (Length(el.weight), i)))
+      .collect::<Vec<_>>();
+
+    let edges = kruskal_indices(self.nodes.len(), &edges)
+      .map(|(_, _, (_, i))| SpanningEdge {
+        portal: i,
+        edge: self.portals[i],
+      })
+      .collect::<Vec<_>>();
+
+    SpanningForest {
+      length: edges.iter().fold(0.0, |sum, el| sum + el.edge.weight),
+      trees: self.nodes.len() - edges.len(),
+      edges,
+    }
+  }
+}
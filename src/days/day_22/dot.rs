@@ -0,0 +1,48 @@
+//! The star map as a GraphViz graph.
+
+use std::{collections::HashSet, fmt::Write};
+
+use super::graph::Graph;
+
+const HIGHLIGHT: &str = "color=\"red\", fontcolor=\"red\", penwidth=2";
+
+/// Every star labelled with its position, and every portal with its length. The stars on the
+/// path and the portals it takes are drawn in red.
+pub fn render(graph: &Graph, path: &[usize]) -> String {
+  let (kind, arrow) = if graph.directed {
+    ("digraph", "->")
+  } else {
+    ("graph", "--")
+  };
+  let on_path = path.iter().copied().collect::<HashSet<_>>();
+  let taken = path
+    .windows(2)
+    .filter_map(|el| graph.edge_between(el[0], el[1]))
+    .map(|el| el.portal)
+    .collect::<HashSet<_>>();
+
+  let mut dot = format!("{kind} stars {{\n  node [shape=circle];\n");
+
+  for (i, [x, y, z]) in graph.nodes.iter().enumerate() {
+    let _ = write!(dot, "  {i} [label=\"{i}\\n({x}, {y}, {z})\"");
+    if on_path.contains(&i) {
+      let _ = write!(dot, ", {HIGHLIGHT}");
+    }
+    dot.push_str("];\n");
+  }
+
+  for (i, portal) in graph.portals.iter().enumerate() {
+    let _ = write!(
+      dot,
+      "  {} {arrow} {} [label=\"{:.3}\"",
+      portal.from, portal.to, portal.weight
+    );
+    if taken.contains(&i) {
+      let _ = write!(dot, ", {HIGHLIGHT}");
+    }
+    dot.push_str("];\n");
+  }
+
+  dot.push_str("}\n");
+  dot
+}
@@ -0,0 +1,291 @@
+//! Stars in 3-D space joined by portals, with the adjacency built once up front.
+
+use std::{cmp::Ordering, ops::Add};
+
+use num_traits::Zero;
+use pathfinding::directed::{astar::astar, bfs::bfs, dijkstra::dijkstra};
+use serde::{Deserialize, Serialize};
+
+pub type Position = [f64; 3];
+
+/// A finite, non-negative distance, ordered so it can be a path cost.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Length(pub f64);
+
+impl PartialEq for Length {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other).is_eq()
+  }
+}
+
+impl Eq for Length {}
+
+impl PartialOrd for Length {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Length {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.0.total_cmp(&other.0)
+  }
+}
+
+impl Add for Length {
+  type Output = Self;
+
+  fn add(self, rhs: Self) -> Self {
+    Self(self.0 + rhs.0)
+  }
+}
+
+impl Zero for Length {
+  fn zero() -> Self {
+    Self(0.0)
+  }
+
+  fn is_zero(&self) -> bool {
+    self.0 == 0.0
+  }
+}
+
+pub fn distance(a: Position, b: Position) -> f64 {
+  a.iter()
+    .zip(b)
+    .map(|(a, b)| (b - a).powi(2))
+    .sum::<f64>()
+    .sqrt()
+}
+
+/// A portal, as given. Without a weight it is as long as the straight line between its ends.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(from = "EdgeInput")]
+pub struct EdgeSpec {
+  pub from: usize,
+  pub to: usize,
+  pub weight: Option<f64>,
+}
+
+/// Either `[from, to]` or `{"from", "to", "weight"}`.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum EdgeInput {
+  Pair(usize, usize),
+  Weighted {
+    from: usize,
+    to: usize,
+    weight: Option<f64>,
+  },
+}
+
+impl From<EdgeInput> for EdgeSpec {
+  fn from(value: EdgeInput) -> Self {
+    match value {
+      EdgeInput::Pair(from, to) => Self {
+        from,
+        to,
+        weight: None,
+      },
+      EdgeInput::Weighted { from, to, weight } => Self { from, to, weight },
+    }
+  }
+}
+
+const fn directed() -> bool {
+  true
+}
+
+/// A graph sent as JSON, with nodes as `[x, y, z]`.
+#[derive(Deserialize, Debug)]
+pub struct GraphSpec {
+  pub nodes: Vec<Position>,
+  #[serde(default)]
+  pub edges: Vec<EdgeSpec>,
+  /// Portals only go one way unless this is false.
+  #[serde(default = "directed")]
+  pub directed: bool,
+}
+
+impl GraphSpec {
+  pub fn build(self) -> Result<Graph, String> {
+    Graph::new(self.nodes, &self.edges, self.directed)
+  }
+}
+
+/// A portal with its weight worked out.
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Portal {
+  pub from: usize,
+  pub to: usize,
+  pub weight: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Edge {
+  pub to: usize,
+  pub weight: f64,
+  /// The index of the portal it goes through.
+  pub portal: usize,
+}
+
+#[derive(Debug)]
+pub struct Graph {
+  pub nodes: Vec<Position>,
+  pub portals: Vec<Portal>,
+  pub directed: bool,
+  /// The edges leaving every node, in the order they were given.
+  adjacency: Vec<Vec<Edge>>,
+  /// The smallest ratio of weight to straight line over all edges. Scaling the A* heuristic by
+  /// it keeps it admissible when weights are shorter than the distances.
+  heuristic_scale: f64,
+}
+
+impl Graph {
+  pub fn new(nodes: Vec<Position>, edges: &[EdgeSpec], directed: bool) -> Result<Self, String> {
+    if let Some(i) = nodes
+      .iter()
+      .position(|el| el.iter().any(|c| !c.is_finite()))
+    {
+      return Err(format!(
+        "Star {i} has a coordinate that is not a finite number"
+      ));
+    }
+
+    let mut adjacency = vec![Vec::new(); nodes.len()];
+    let mut portals = Vec::with_capacity(edges.len());
+    let mut heuristic_scale = 1_f64;
+
+    for (i, &EdgeSpec { from, to, weight }) in edges.iter().enumerate() {
+      if from >= nodes.len() || to >= nodes.len() {
+        return Err(format!(
+          "Portal {i} joins {from} and {to}, but there are only {} stars",
+          nodes.len()
+        ));
+      }
+
+      let straight = distance(nodes[from], nodes[to]);
+      let weight = weight.unwrap_or(straight);
+      if !weight.is_finite() || weight < 0.0 {
+        return Err(format!(
+          "Portal {i} has weight {weight}, which is not a length"
+        ));
+      }
+      if straight > 0.0 {
+        heuristic_scale = heuristic_scale.min(weight / straight);
+      }
+
+      portals.push(Portal { from, to, weight });
+      adjacency[from].push(Edge {
+        to,
+        weight,
+        portal: i,
+      });
+      if !directed && from != to {
+        adjacency[to].push(Edge {
+          to: from,
+          weight,
+          portal: i,
+        });
+      }
+    }
+
+    Ok(Self {
+      nodes,
+      portals,
+      directed,
+      adjacency,
+      heuristic_scale,
+    })
+  }
+
+  /// An error unless the star is on the map.
+  pub fn star(&self, node: usize) -> Result<usize, String> {
+    if node < self.nodes.len() {
+      Ok(node)
+    } else {
+      Err(format!(
+        "There is no star {node}, there are {} stars",
+        self.nodes.len()
+      ))
+    }
+  }
+
+  pub fn edges(&self, node: usize) -> &[Edge] {
+    &self.adjacency[node]
+  }
+
+  pub(super) fn successors(&self, node: usize) -> impl Iterator<Item = (usize, Length)> + '_ {
+    self.adjacency[node]
+      .iter()
+      .map(|el| (el.to, Length(el.weight)))
+  }
+
+  /// The shortest portal from one star to the other.
+  pub fn edge_between(&self, from: usize, to: usize) -> Option<&Edge> {
+    self.adjacency[from]
+      .iter()
+      .filter(|el| el.to == to)
+      .min_by(|a, b| a.weight.total_cmp(&b.weight))
+  }
+
+  /// The length of a path along the shortest portal between each pair of stars on it.
+  pub fn path_length(&self, path: &[usize]) -> f64 {
+    path
+      .windows(2)
+      .filter_map(|el| self.edge_between(el[0], el[1]))
+      .fold(0.0, |sum, el| sum + el.weight)
+  }
+
+  pub fn route(&self, start: usize, goal: usize, algorithm: Algorithm) -> Option<Route> {
+    let path = match algorithm {
+      Algorithm::Bfs => bfs(
+        &start,
+        |&node| self.adjacency[node].iter().map(|el| el.to),
+        |&node| node == goal,
+      )?,
+      Algorithm::Dijkstra => {
+        dijkstra(&start, |&node| self.successors(node), |&node| node == goal)?.0
+      }
+      Algorithm::Astar => {
+        let goal_position = self.nodes[goal];
+        astar(
+          &start,
+          |&node| self.successors(node),
+          |&node| Length(distance(self.nodes[node], goal_position) * self.heuristic_scale),
+          |&node| node == goal,
+        )?
+        .0
+      }
+    };
+
+    Some(self.route_along(path))
+  }
+
+  pub fn route_along(&self, path: Vec<usize>) -> Route {
+    Route {
+      hops: path.len().saturating_sub(1),
+      length: self.path_length(&path),
+      path,
+    }
+  }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+  /// Fewest portals.
+  Bfs,
+  /// Shortest distance.
+  #[default]
+  Dijkstra,
+  /// Shortest distance, searching towards the goal first.
+  Astar,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Route {
+  pub path: Vec<usize>,
+  pub hops: usize,
+  pub length: f64,
+}
@@ -0,0 +1,387 @@
+//! The line based inputs of day 22, or the same as JSON, with every problem reported by where
+//! it is instead of a panic.
+//!
+//! Blank lines are skipped, lines may end in CRLF and fields are separated by any whitespace.
+
+use std::{fmt::Display, str::FromStr};
+
+use axum::{
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Json,
+};
+use serde::Serialize;
+
+use super::graph::{EdgeSpec, Graph, GraphSpec};
+
+/// Parsing gives up after this many errors.
+const MAX_ERRORS: usize = 100;
+
+#[derive(Serialize, Debug)]
+pub struct InputError {
+  /// 1-based, counting blank lines too.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub line: Option<usize>,
+  /// 1-based, in characters.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub column: Option<usize>,
+  /// A JSON pointer to the value, for JSON that parsed but doesn't make sense.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub pointer: Option<String>,
+  pub error: String,
+}
+
+impl InputError {
+  const fn at(line: usize, column: usize, error: String) -> Self {
+    Self {
+      line: Some(line),
+      column: Some(column),
+      pointer: None,
+      error,
+    }
+  }
+
+  fn json(e: &serde_json::Error) -> Self {
+    Self::at(e.line(), e.column(), e.to_string())
+  }
+
+  const fn in_json(pointer: String, error: String) -> Self {
+    Self {
+      line: None,
+      column: None,
+      pointer: Some(pointer),
+      error,
+    }
+  }
+
+  /// For the input as a whole.
+  pub const fn anywhere(error: String) -> Self {
+    Self {
+      line: None,
+      column: None,
+      pointer: None,
+      error,
+    }
+  }
+}
+
+/// Sent as a 400 with `{"errors": [...]}`.
+#[derive(Serialize, Debug)]
+pub struct InputErrors {
+  pub errors: Vec<InputError>,
+}
+
+impl IntoResponse for InputErrors {
+  fn into_response(self) -> Response {
+    (StatusCode::BAD_REQUEST, Json(self)).into_response()
+  }
+}
+
+impl From<Vec<InputError>> for InputErrors {
+  fn from(errors: Vec<InputError>) -> Self {
+    Self { errors }
+  }
+}
+
+struct Field<'a> {
+  column: usize,
+  text: &'a str,
+}
+
+/// The fields of a line with the columns they start at.
+fn fields(line: &str) -> Vec<Field<'_>> {
+  let mut fields = Vec::new();
+  let mut start = None;
+
+  for (column, (i, c)) in line.char_indices().enumerate() {
+    match (start, c.is_whitespace()) {
+      (None, false) => start = Some((column + 1, i)),
+      (Some((column, from)), true) => {
+        fields.push(Field {
+          column,
+          text: &line[from..i],
+        });
+        start = None;
+      }
+      _ => {}
+    }
+  }
+  if let Some((column, from)) = start {
+    fields.push(Field {
+      column,
+      text: &line[from..],
+    });
+  }
+
+  fields
+}
+
+struct Parser<'a> {
+  lines: std::iter::Enumerate<std::str::Lines<'a>>,
+  /// The number of the last line read, blank or not.
+  line: usize,
+  errors: Vec<InputError>,
+}
+
+impl<'a> Parser<'a> {
+  fn new(input: &'a str) -> Self {
+    Self {
+      lines: input.lines().enumerate(),
+      line: 0,
+      errors: Vec::new(),
+    }
+  }
+
+  const fn full(&self) -> bool {
+    self.errors.len() >= MAX_ERRORS
+  }
+
+  fn error(&mut self, line: usize, column: usize, error: String) {
+    if !self.full() {
+      self.errors.push(InputError::at(line, column, error));
+    }
+  }
+
+  /// The next line that isn't blank, with its number and fields.
+  fn next_line(&mut self) -> Option<(usize, Vec<Field<'a>>)> {
+    for (i, line) in self.lines.by_ref() {
+      self.line = i + 1;
+      let fields = fields(line);
+      if !fields.is_empty() {
+        return Some((self.line, fields));
+      }
+    }
+
+    None
+  }
+
+  /// Reports running out of lines where the next one would have been.
+  fn truncated(&mut self, error: String) {
+    self.error(self.line + 1, 1, error);
+  }
+
+  /// Exactly `count` fields, or an error at the first missing or extra one.
+  fn expect_fields(&mut self, line: usize, fields: &[Field], count: usize, what: &str) -> bool {
+    match fields.get(count) {
+      Some(extra) => {
+        self.error(
+          line,
+          extra.column,
+          format!("Expected {count} {what}, found {}", fields.len()),
+        );
+        false
+      }
+      None if fields.len() < count => {
+        let end = fields
+          .last()
+          .map_or(1, |el| el.column + el.text.chars().count());
+        self.error(
+          line,
+          end,
+          format!("Expected {count} {what}, found {}", fields.len()),
+        );
+        false
+      }
+      None => true,
+    }
+  }
+
+  fn parse<T>(&mut self, line: usize, field: &Field, what: &str) -> Option<T>
+  where
+    T: FromStr,
+    T::Err: Display,
+  {
+    field
+      .text
+      .parse()
+      .map_err(|e| {
+        self.error(
+          line,
+          field.column,
+          format!("Invalid {what} `{}`: {e}", field.text),
+        )
+      })
+      .ok()
+  }
+
+  /// A line holding just a count.
+  fn count(&mut self, what: &str) -> Option<usize> {
+    let Some((line, fields)) = self.next_line() else {
+      self.truncated(format!("Expected the number of {what}"));
+      return None;
+    };
+    if !self.expect_fields(line, &fields, 1, "number") {
+      return None;
+    }
+
+    self.parse(line, &fields[0], &format!("number of {what}"))
+  }
+
+  /// Reports any line left after everything expected was read.
+  fn finish(&mut self) {
+    if let Some((line, fields)) = self.next_line() {
+      self.error(
+        line,
+        fields[0].column,
+        "Unexpected line after the end of the input".to_string(),
+      );
+    }
+  }
+
+  fn result<T>(self, value: T) -> Result<T, InputErrors> {
+    if self.errors.is_empty() {
+      Ok(value)
+    } else {
+      Err(self.errors.into())
+    }
+  }
+}
+
+/// One number per line, or a JSON list of them.
+pub fn integers(input: &str, json: bool) -> Result<Vec<u64>, InputErrors> {
+  if json {
+    return serde_json::from_str(input).map_err(|e| vec![InputError::json(&e)].into());
+  }
+
+  let mut parser = Parser::new(input);
+  let mut numbers = Vec::new();
+
+  while let Some((line, fields)) = parser.next_line() {
+    if parser.expect_fields(line, &fields, 1, "number") {
+      numbers.extend(parser.parse::<u64>(line, &fields[0], "number"));
+    }
+    if parser.full() {
+      break;
+    }
+  }
+
+  parser.result(numbers)
+}
+
+/// The number of stars and a line of `x y z` for each, then the number of portals and a line of
+/// `from to` for each. As JSON it is the graph `/22/route` takes.
+pub fn star_map(input: &str, json: bool) -> Result<Graph, InputErrors> {
+  if json {
+    return json_star_map(input);
+  }
+
+  let mut parser = Parser::new(input);
+
+  let Some(star_count) = parser.count("stars") else {
+    return Err(parser.errors.into());
+  };
+  if star_count == 0 {
+    parser.error(
+      parser.line,
+      1,
+      "There has to be at least one star".to_string(),
+    );
+  }
+
+  let mut stars = Vec::new();
+  for i in 0..star_count {
+    let Some((line, fields)) = parser.next_line() else {
+      parser.truncated(format!("Expected {star_count} stars, found {i}"));
+      return Err(parser.errors.into());
+    };
+    if !parser.expect_fields(line, &fields, 3, "coordinates") {
+      continue;
+    }
+
+    let coordinates = fields
+      .iter()
+      .map(|el| parser.parse::<i32>(line, el, "coordinate"))
+      .collect::<Vec<_>>();
+    if let [Some(x), Some(y), Some(z)] = coordinates[..] {
+      stars.push([f64::from(x), f64::from(y), f64::from(z)]);
+    }
+    if parser.full() {
+      return Err(parser.errors.into());
+    }
+  }
+
+  let Some(portal_count) = parser.count("portals") else {
+    return Err(parser.errors.into());
+  };
+
+  let mut portals = Vec::new();
+  for i in 0..portal_count {
+    let Some((line, fields)) = parser.next_line() else {
+      parser.truncated(format!("Expected {portal_count} portals, found {i}"));
+      return Err(parser.errors.into());
+    };
+    if !parser.expect_fields(line, &fields, 2, "stars") {
+      continue;
+    }
+
+    let ends = fields
+      .iter()
+      .map(|field| {
+        let star = parser.parse::<usize>(line, field, "star")?;
+        if star >= star_count {
+          parser.error(
+            line,
+            field.column,
+            format!("There is no star {star}, there are {star_count} stars"),
+          );
+          return None;
+        }
+        Some(star)
+      })
+      .collect::<Vec<_>>();
+    if let [Some(from), Some(to)] = ends[..] {
+      portals.push(EdgeSpec {
+        from,
+        to,
+        weight: None,
+      });
+    }
+    if parser.full() {
+      return Err(parser.errors.into());
+    }
+  }
+
+  parser.finish();
+  parser.result(())?;
+
+  Graph::new(stars, &portals, true).map_err(|e| vec![InputError::anywhere(e)].into())
+}
+
+fn json_star_map(input: &str) -> Result<Graph, InputErrors> {
+  let spec = serde_json::from_str::<GraphSpec>(input).map_err(|e| vec![InputError::json(&e)])?;
+
+  let mut errors = Vec::new();
+  if spec.nodes.is_empty() {
+    errors.push(InputError::in_json(
+      "/nodes".to_string(),
+      "There has to be at least one star".to_string(),
+    ));
+  }
+  for (i, edge) in spec.edges.iter().enumerate() {
+    let error = if let Some(star) = [edge.from, edge.to]
+      .into_iter()
+      .find(|el| *el >= spec.nodes.len())
+    {
+      format!(
+        "There is no star {star}, there are {} stars",
+        spec.nodes.len()
+      )
+    } else if edge.weight.is_some_and(|el| el < 0.0) {
+      "Weights can't be negative".to_string()
+    } else {
+      continue;
+    };
+
+    errors.push(InputError::in_json(format!("/edges/{i}"), error));
+    if errors.len() >= MAX_ERRORS {
+      break;
+    }
+  }
+  if !errors.is_empty() {
+    return Err(errors.into());
+  }
+
+  spec
+    .build()
+    .map_err(|e| vec![InputError::anywhere(e)].into())
+}
@@ -0,0 +1,254 @@
+use anyhow::anyhow;
+use axum::{
+  http::{header, HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  routing::post,
+  Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{AppError, BadRequest};
+use analysis::SpanningForest;
+use graph::{Algorithm, Graph, GraphSpec, Route};
+use input::{InputError, InputErrors};
+
+mod analysis;
+mod dot;
+mod graph;
+mod input;
+
+/// How many routes `/22/map/paths` finds at most.
+const MAX_PATHS: usize = 32;
+
+/// How many presents `/22/integers` wraps at most, 4 bytes each.
+const MAX_GIFTS: u64 = 1 << 20;
+
+pub fn get_routes() -> Router {
+  Router::new()
+    .route("/22/integers", post(task_1))
+    .route("/22/rocket", post(task_2))
+    .route("/22/route", post(route))
+    .route("/22/map/reachable", post(reachable))
+    .route("/22/map/components", post(components))
+    .route("/22/map/cycle", post(cycle))
+    .route("/22/map/paths", post(paths))
+    .route("/22/map/spanning-tree", post(spanning_tree))
+    .route("/22/map/dot", post(dot))
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+  headers
+    .get(header::CONTENT_TYPE)
+    .and_then(|el| el.to_str().ok())
+    .is_some_and(|el| el.contains("application/json"))
+}
+
+async fn task_1(headers: HeaderMap, payload: String) -> Result<Response, AppError> {
+  let numbers = match input::integers(&payload, is_json(&headers)) {
+    Ok(el) => el,
+    Err(e) => return Ok(e.into_response()),
+  };
+  let num = numbers.into_iter().fold(0u64, |acc, el| acc ^ el);
+  if num > MAX_GIFTS {
+    return Ok(
+      InputErrors::from(vec![InputError::anywhere(format!(
+        "The lonely number is {num}, but at most {MAX_GIFTS} presents can be wrapped"
+      ))])
+      .into_response(),
+    );
+  }
+
+  Ok("🎁".repeat(usize::try_from(num)?).into_response())
+}
+
+async fn task_2(headers: HeaderMap, payload: String) -> Result<Response, AppError> {
+  // If you are reading this, probably not the cleanest idea to use a whole ahh crate for this
+  // I just wanted to check out how it works, I saw it a while back and never tried it out :)
+  let graph = match input::star_map(&payload, is_json(&headers)) {
+    Ok(el) => el,
+    Err(e) => return Ok(e.into_response()),
+  };
+
+  let route = graph
+    .route(0, graph.nodes.len() - 1, Algorithm::Bfs)
+    .ok_or_else(|| anyhow!("Path not found"))?;
+
+  Ok(format!("{} {:.3}", route.hops, route.length).into_response())
+}
+
+/// Where a route starts and ends, the first and the last star when left out.
+#[derive(Deserialize, Debug)]
+struct Ends {
+  start: Option<usize>,
+  goal: Option<usize>,
+}
+
+impl Ends {
+  fn resolve(&self, graph: &Graph) -> Result<(usize, usize), BadRequest> {
+    let start = self.start.unwrap_or(0);
+    let goal = self
+      .goal
+      .unwrap_or_else(|| graph.nodes.len().saturating_sub(1));
+
+    Ok((
+      graph.star(start).map_err(BadRequest)?,
+      graph.star(goal).map_err(BadRequest)?,
+    ))
+  }
+}
+
+#[derive(Deserialize, Debug)]
+struct RouteBody {
+  #[serde(flatten)]
+  graph: GraphSpec,
+  #[serde(flatten)]
+  ends: Ends,
+  #[serde(default)]
+  algorithm: Algorithm,
+}
+
+async fn route(Json(payload): Json<RouteBody>) -> Result<Response, AppError> {
+  let graph = payload.graph.build().map_err(BadRequest)?;
+  let (start, goal) = payload.ends.resolve(&graph)?;
+
+  Ok(match graph.route(start, goal, payload.algorithm) {
+    Some(route) => Json(route).into_response(),
+    None => StatusCode::NOT_FOUND.into_response(),
+  })
+}
+
+#[derive(Deserialize, Debug)]
+struct ReachableBody {
+  #[serde(flatten)]
+  graph: GraphSpec,
+  #[serde(default)]
+  from: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct Reachable {
+  from: usize,
+  reachable: Vec<usize>,
+}
+
+async fn reachable(Json(payload): Json<ReachableBody>) -> Result<Json<Reachable>, AppError> {
+  let graph = payload.graph.build().map_err(BadRequest)?;
+  let from = graph.star(payload.from).map_err(BadRequest)?;
+
+  Ok(Json(Reachable {
+    from,
+    reachable: graph.reachable(from),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+struct Components {
+  components: Vec<Vec<usize>>,
+  /// Only for one-way portals, with portals going both ways they are the components.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  strongly_connected: Option<Vec<Vec<usize>>>,
+}
+
+async fn components(Json(payload): Json<GraphSpec>) -> Result<Json<Components>, AppError> {
+  let graph = payload.build().map_err(BadRequest)?;
+
+  Ok(Json(Components {
+    components: graph.components(),
+    strongly_connected: graph.directed.then(|| graph.strongly_connected()),
+  }))
+}
+
+#[derive(Serialize, Debug)]
+struct Cycle {
+  acyclic: bool,
+  cycle: Option<Vec<usize>>,
+}
+
+async fn cycle(Json(payload): Json<GraphSpec>) -> Result<Json<Cycle>, AppError> {
+  let graph = payload.build().map_err(BadRequest)?;
+  let cycle = graph.find_cycle();
+
+  Ok(Json(Cycle {
+    acyclic: cycle.is_none(),
+    cycle,
+  }))
+}
+
+const fn three() -> usize {
+  3
+}
+
+#[derive(Deserialize, Debug)]
+struct PathsBody {
+  #[serde(flatten)]
+  graph: GraphSpec,
+  #[serde(flatten)]
+  ends: Ends,
+  #[serde(default = "three")]
+  k: usize,
+}
+
+async fn paths(Json(payload): Json<PathsBody>) -> Result<Json<Vec<Route>>, AppError> {
+  if !(1..=MAX_PATHS).contains(&payload.k) {
+    Err(BadRequest(format!(
+      "Between 1 and {MAX_PATHS} paths can be asked for"
+    )))?;
+  }
+
+  let graph = payload.graph.build().map_err(BadRequest)?;
+  let (start, goal) = payload.ends.resolve(&graph)?;
+
+  Ok(Json(graph.k_shortest(start, goal, payload.k)))
+}
+
+async fn spanning_tree(Json(payload): Json<GraphSpec>) -> Result<Json<SpanningForest>, AppError> {
+  let graph = payload.build().map_err(BadRequest)?;
+
+  Ok(Json(graph.spanning_forest()))
+}
+
+#[derive(Deserialize, Debug)]
+struct DotBody {
+  #[serde(flatten)]
+  graph: GraphSpec,
+  /// The stars to highlight, in order.
+  path: Option<Vec<usize>>,
+  /// Otherwise the route between these is highlighted, if they or an algorithm are given.
+  #[serde(flatten)]
+  ends: Ends,
+  algorithm: Option<Algorithm>,
+}
+
+async fn dot(Json(payload): Json<DotBody>) -> Result<Response, AppError> {
+  let DotBody {
+    graph,
+    path,
+    ends,
+    algorithm,
+  } = payload;
+  let graph = graph.build().map_err(BadRequest)?;
+
+  let path = match path {
+    Some(path) => path
+      .into_iter()
+      .map(|el| graph.star(el))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(BadRequest)?,
+    None if ends.start.is_some() || ends.goal.is_some() || algorithm.is_some() => {
+      let (start, goal) = ends.resolve(&graph)?;
+      graph
+        .route(start, goal, algorithm.unwrap_or_default())
+        .map(|el| el.path)
+        .unwrap_or_default()
+    }
+    None => Vec::new(),
+  };
+
+  Ok(
+    (
+      [(header::CONTENT_TYPE, "text/vnd.graphviz")],
+      dot::render(&graph, &path),
+    )
+      .into_response(),
+  )
+}
@@ -1,5 +1,7 @@
 use axum::{http::StatusCode, response::IntoResponse};
 
+use crate::auth::AuthError;
+
 pub mod day_01;
 pub mod day_04;
 pub mod day_05;
@@ -22,9 +24,18 @@ pub struct AppError(anyhow::Error);
 
 impl IntoResponse for AppError {
   fn into_response(self) -> axum::response::Response {
+    if let Some(BadRequest(msg)) = self.0.downcast_ref::<BadRequest>() {
+      return (StatusCode::BAD_REQUEST, msg.clone()).into_response();
+    }
+
+    let err = match self.0.downcast::<AuthError>() {
+      Ok(e) => return e.into_response(),
+      Err(err) => err,
+    };
+
     (
       StatusCode::INTERNAL_SERVER_ERROR,
-      format!("Something went wrong: {}", self.0),
+      format!("Something went wrong: {err}"),
     )
       .into_response()
   }
@@ -35,3 +46,15 @@ impl<E: Into<anyhow::Error>> From<E> for AppError {
     Self(err.into())
   }
 }
+
+/// Wrap this in an [`AppError`] to respond with a 400 instead of a 500.
+#[derive(Debug)]
+pub struct BadRequest(pub String);
+
+impl std::fmt::Display for BadRequest {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl std::error::Error for BadRequest {}
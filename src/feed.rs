@@ -0,0 +1,209 @@
+use std::time::Duration;
+
+use axum::{
+  extract::{
+    ws::{
+      Message::{Close, Text},
+      WebSocket,
+    },
+    Query, State, WebSocketUpgrade,
+  },
+  response::{
+    sse::{Event, KeepAlive, Sse},
+    IntoResponse, Response,
+  },
+  routing::get,
+  Router,
+};
+use futures_util::stream::{self, Stream};
+use serde_json::json;
+use sqlx::{postgres::PgListener, PgExecutor, PgPool};
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+use tracing::warn;
+
+use crate::days::{day_13::Order, day_18::Region};
+
+/// The Postgres channel every change is published on.
+pub(crate) const CHANNEL: &str = "changes";
+
+pub fn get_routes(pool: PgPool) -> Router {
+  let state = FeedState {
+    changes: broadcast::channel(1024).0,
+  };
+
+  spawn_listener(pool, state.changes.clone());
+
+  Router::new()
+    .route("/orders/stream", get(stream_changes))
+    .with_state(state)
+}
+
+#[derive(Clone, Debug)]
+struct FeedState {
+  changes: Sender<Change>,
+}
+
+/// A change to `orders` or `regions`. The serialized form doubles as the `NOTIFY` payload, which
+/// is why [`crate::bulk`] builds the same shape in SQL.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "row", rename_all = "snake_case")]
+pub(crate) enum Change {
+  OrderInserted(Order),
+  RegionInserted(Region),
+}
+
+/// Publishes a change to every instance listening on [`CHANNEL`]. Inside a transaction, the
+/// notification is only delivered once it commits.
+pub(crate) async fn publish<'e>(
+  executor: impl PgExecutor<'e>,
+  change: &Change,
+) -> Result<(), anyhow::Error> {
+  let payload = serde_json::to_string(change)?;
+
+  let _ = sqlx::query!("SELECT pg_notify($1, $2)", CHANNEL, payload)
+    .execute(executor)
+    .await?;
+
+  Ok(())
+}
+
+fn spawn_listener(pool: PgPool, sender: Sender<Change>) {
+  tokio::spawn(async move {
+    loop {
+      if let Err(e) = listen(&pool, &sender).await {
+        warn!("Change feed listener failed: {:?}", e);
+      }
+
+      tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+  });
+}
+
+async fn listen(pool: &PgPool, sender: &Sender<Change>) -> Result<(), anyhow::Error> {
+  let mut listener = PgListener::connect_with(pool).await?;
+  listener.listen(CHANNEL).await?;
+
+  loop {
+    let notification = listener.recv().await?;
+
+    match serde_json::from_str::<Change>(notification.payload()) {
+      // No subscribers is not an error
+      Ok(change) => drop(sender.send(change)),
+      Err(e) => warn!(
+        "Failed to parse change {:?}: {:?}",
+        notification.payload(),
+        e
+      ),
+    }
+  }
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct StreamFilter {
+  region_id: Option<i32>,
+  gift_name: Option<String>,
+}
+
+impl StreamFilter {
+  fn matches(&self, change: &Change) -> bool {
+    match change {
+      Change::OrderInserted(order) => {
+        self.region_id.is_none_or(|el| el == order.region_id)
+          && self
+            .gift_name
+            .as_ref()
+            .is_none_or(|el| *el == order.gift_name)
+      }
+      Change::RegionInserted(region) => {
+        self.region_id.is_none_or(|el| el == region.id) && self.gift_name.is_none()
+      }
+    }
+  }
+}
+
+/// What a subscriber gets next: a change, or how many changes it fell behind by.
+enum Update {
+  Change(Change),
+  Lagged(u64),
+}
+
+impl Update {
+  fn to_json(&self) -> String {
+    match self {
+      Self::Change(change) => serde_json::to_string(change).unwrap_or_default(),
+      Self::Lagged(missed) => json!({ "kind": "lagged", "missed": missed }).to_string(),
+    }
+  }
+}
+
+async fn next_update(receiver: &mut Receiver<Change>, filter: &StreamFilter) -> Option<Update> {
+  loop {
+    match receiver.recv().await {
+      Ok(change) if filter.matches(&change) => return Some(Update::Change(change)),
+      Ok(_) => {}
+      Err(RecvError::Lagged(missed)) => return Some(Update::Lagged(missed)),
+      Err(RecvError::Closed) => return None,
+    }
+  }
+}
+
+/// Serves the feed as a WebSocket when the client asks for an upgrade, and as SSE otherwise.
+async fn stream_changes(
+  ws: Option<WebSocketUpgrade>,
+  Query(filter): Query<StreamFilter>,
+  State(state): State<FeedState>,
+) -> Response {
+  let receiver = state.changes.subscribe();
+
+  match ws {
+    Some(ws) => ws.on_upgrade(move |socket| handle_socket(socket, receiver, filter)),
+    None => Sse::new(sse_stream(receiver, filter))
+      .keep_alive(KeepAlive::default())
+      .into_response(),
+  }
+}
+
+fn sse_stream(
+  receiver: Receiver<Change>,
+  filter: StreamFilter,
+) -> impl Stream<Item = Result<Event, axum::Error>> {
+  stream::unfold((receiver, filter), |(mut receiver, filter)| async move {
+    let update = next_update(&mut receiver, &filter).await?;
+    let event = match &update {
+      Update::Change(Change::OrderInserted(_)) => "order_inserted",
+      Update::Change(Change::RegionInserted(_)) => "region_inserted",
+      Update::Lagged(_) => "lagged",
+    };
+
+    Some((
+      Ok(Event::default().event(event).data(update.to_json())),
+      (receiver, filter),
+    ))
+  })
+}
+
+async fn handle_socket(
+  mut socket: WebSocket,
+  mut receiver: Receiver<Change>,
+  filter: StreamFilter,
+) {
+  loop {
+    tokio::select! {
+      update = next_update(&mut receiver, &filter) => {
+        let Some(update) = update else {
+          return;
+        };
+
+        if socket.send(Text(update.to_json())).await.is_err() {
+          return;
+        }
+      }
+      msg = socket.recv() => {
+        // The feed is one way, anything other than a close is ignored
+        if matches!(msg, None | Some(Err(_) | Ok(Close(_)))) {
+          return;
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,207 @@
+//! Paging through list responses.
+//!
+//! Handlers take a [`Pagination`] from `?offset=&limit=` (or the opaque `?cursor=` from a `Link`
+//! header) and `?split=` to cut the page into chunks, and answer with the [`Page`] it makes of
+//! their list. Pages tell the total in `X-Total-Count` and link to the first, previous and next
+//! page in `Link`.
+
+use axum::{
+  async_trait,
+  extract::{FromRequestParts, OriginalUri},
+  http::{header, request::Parts, HeaderName, HeaderValue},
+  response::{IntoResponse, Response},
+  Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::days::{AppError, BadRequest};
+
+/// The largest limit that can be asked for. Without one a page runs to the end of the list.
+pub const MAX_LIMIT: usize = 1000;
+
+const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// Where a page starts, how long it is and how to chunk it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Cursor {
+  offset: usize,
+  limit: Option<usize>,
+  split: Option<usize>,
+}
+
+impl Cursor {
+  fn encode(&self) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("Cursor serializes"))
+  }
+
+  fn decode(token: &str) -> Result<Self, BadRequest> {
+    URL_SAFE_NO_PAD
+      .decode(token)
+      .ok()
+      .and_then(|el| serde_json::from_slice(&el).ok())
+      .ok_or_else(|| BadRequest("Invalid cursor".to_string()))
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pagination {
+  cursor: Cursor,
+  path: String,
+  /// The query parameters that aren't about paging, kept as they were for the links.
+  rest: Vec<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Pagination {
+  type Rejection = AppError;
+
+  async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    // The handler may sit in a nested router, the links have to point at the full path
+    let uri = parts
+      .extensions
+      .get::<OriginalUri>()
+      .map_or(&parts.uri, |el| &el.0);
+
+    Ok(Self::parse(uri.path(), uri.query().unwrap_or_default())?)
+  }
+}
+
+impl Pagination {
+  fn parse(path: &str, query: &str) -> Result<Self, BadRequest> {
+    let number = |name: &str, value: &str| {
+      value.parse::<usize>().map_err(|_| {
+        BadRequest(format!(
+          "{name} must be a non-negative integer, not {value:?}"
+        ))
+      })
+    };
+
+    let mut offset = None;
+    let mut limit = None;
+    let mut split = None;
+    let mut cursor = None;
+    let mut rest = Vec::new();
+
+    for pair in query.split('&').filter(|el| !el.is_empty()) {
+      let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+      match name {
+        "offset" => offset = Some(number(name, value)?),
+        "limit" => limit = Some(number(name, value)?),
+        "split" => split = Some(number(name, value)?),
+        "cursor" => cursor = Some(Cursor::decode(value)?),
+        _ => rest.push(pair.to_string()),
+      }
+    }
+
+    let cursor = match (cursor, offset) {
+      (Some(_), Some(_)) => {
+        return Err(BadRequest(
+          "offset cannot be combined with cursor".to_string(),
+        ))
+      }
+      // limit and split may still be changed while following a cursor
+      (Some(cursor), None) => Cursor {
+        offset: cursor.offset,
+        limit: limit.or(cursor.limit),
+        split: split.or(cursor.split),
+      },
+      (None, offset) => Cursor {
+        offset: offset.unwrap_or_default(),
+        limit,
+        split,
+      },
+    };
+
+    if cursor.limit.is_some_and(|el| el > MAX_LIMIT) {
+      return Err(BadRequest(format!("limit must be at most {MAX_LIMIT}")));
+    }
+    if cursor.split == Some(0) {
+      return Err(BadRequest("split must be at least 1".to_string()));
+    }
+
+    Ok(Self {
+      cursor,
+      path: path.to_string(),
+      rest,
+    })
+  }
+
+  pub const fn offset(&self) -> usize {
+    self.cursor.offset
+  }
+
+  /// Everything after the offset when no limit was given, so lists are never cut short silently.
+  pub fn limit(&self) -> usize {
+    self.cursor.limit.unwrap_or(usize::MAX)
+  }
+
+  /// Cuts the page out of the whole list.
+  pub fn page<T>(self, items: Vec<T>) -> Page<T> {
+    let total = items.len();
+    let items = items
+      .into_iter()
+      .skip(self.offset())
+      .take(self.limit())
+      .collect();
+
+    Page {
+      items,
+      total,
+      pagination: self,
+    }
+  }
+
+  fn link(&self, offset: usize, rel: &str) -> String {
+    let cursor = Cursor {
+      offset,
+      ..self.cursor
+    };
+
+    let mut query = self.rest.clone();
+    query.push(format!("cursor={}", cursor.encode()));
+
+    format!("<{}?{}>; rel=\"{rel}\"", self.path, query.join("&"))
+  }
+
+  fn links(&self, total: usize) -> Vec<String> {
+    let offset = self.offset();
+    let limit = self.limit();
+
+    let mut links = vec![self.link(0, "first")];
+    if offset > 0 {
+      links.push(self.link(offset.saturating_sub(limit), "prev"));
+    }
+    // A zero limit would link to the same page forever
+    if limit > 0 && offset.saturating_add(limit) < total {
+      links.push(self.link(offset + limit, "next"));
+    }
+
+    links
+  }
+}
+
+/// One page of a list, a JSON array of the items or of chunks of them.
+#[derive(Debug)]
+pub struct Page<T> {
+  items: Vec<T>,
+  total: usize,
+  pagination: Pagination,
+}
+
+impl<T: Serialize> IntoResponse for Page<T> {
+  fn into_response(self) -> Response {
+    let mut res = match self.pagination.cursor.split {
+      Some(split) => Json(self.items.chunks(split).collect::<Vec<_>>()).into_response(),
+      None => Json(&self.items).into_response(),
+    };
+
+    let headers = res.headers_mut();
+    let _ = headers.insert(X_TOTAL_COUNT, HeaderValue::from(self.total));
+    if let Ok(links) = HeaderValue::try_from(self.pagination.links(self.total).join(", ")) {
+      let _ = headers.insert(header::LINK, links);
+    }
+
+    res
+  }
+}